@@ -0,0 +1,402 @@
+use crate::s_exp_parser::{SExpr, SExprRef};
+use crate::value::*;
+
+
+/// a field that couldn't be read. field is a select path relative to the element,
+/// like at/1 for the y coordinate of its position
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldError {
+	pub field : String,
+	pub kind : FieldErrorKind,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldErrorKind {
+	Missing,
+	Invalid{expected : &'static str, found : String},
+}
+
+
+impl FieldError {
+	pub fn missing(field : &str) -> Self {
+		return FieldError{field : field.to_string(), kind : FieldErrorKind::Missing};
+	}
+
+	pub fn invalid(field : &str, expected : &'static str, found : &Value) -> Self {
+		return FieldError{
+			field : field.to_string(),
+			kind : FieldErrorKind::Invalid{expected : expected, found : value_as_string(found)},
+		};
+	}
+
+	/// the same error, seen from the expression that holds name
+	pub(crate) fn inside(self, name : &str) -> Self {
+		return FieldError{field : format!("{}/{}", name, self.field), kind : self.kind};
+	}
+}
+
+
+impl std::fmt::Display for FieldError {
+	fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+		match &self.kind {
+			FieldErrorKind::Missing => write!(f, "{} is missing", self.field),
+			FieldErrorKind::Invalid{expected, found} => write!(f, "{} should be {}, found {}", self.field, expected, found),
+		}
+	}
+}
+
+
+/// types that are a single value of an expression
+pub trait FromValue : Sized {
+	const EXPECTED : &'static str; // what the value should have been, for errors
+
+	fn from_value(value : &Value) -> Option<Self>;
+}
+
+impl FromValue for f64 {
+	const EXPECTED : &'static str = "a number";
+
+	fn from_value(value : &Value) -> Option<Self> {
+		return value_to_float(value);
+	}
+}
+
+impl FromValue for isize {
+	const EXPECTED : &'static str = "an integer";
+
+	fn from_value(value : &Value) -> Option<Self> {
+		return value_to_int(value);
+	}
+}
+
+impl FromValue for usize {
+	const EXPECTED : &'static str = "a positive integer";
+
+	fn from_value(value : &Value) -> Option<Self> {
+		return value_to_int(value).and_then(|x| x.try_into().ok());
+	}
+}
+
+impl FromValue for String {
+	const EXPECTED : &'static str = "a string";
+
+	fn from_value(value : &Value) -> Option<Self> {
+		return value_to_string(value);
+	}
+}
+
+
+/// the value at position of exp, counting from the first one after the name
+pub fn value_at<T : FromValue>(exp : &SExprRef, position : usize) -> Result<T, FieldError> {
+	let field = position.to_string();
+
+	return match exp.values().get(position) {
+		Some(value) => T::from_value(value).ok_or_else(|| FieldError::invalid(&field, T::EXPECTED, value)),
+		None => Err(FieldError::missing(&field)),
+	};
+}
+
+
+/// types that can be read from an expression. exp is what follows the name,
+/// like 1 2 for (at 1 2)
+pub trait FromSExpr : Sized {
+	fn from_s_expr(exp : &SExprRef) -> Result<Self, FieldError>;
+
+	/// what a field that isn't there at all becomes. only optional fields have a value for that
+	fn from_missing(field : &str) -> Result<Self, FieldError> {
+		return Err(FieldError::missing(field));
+	}
+}
+
+impl<T : FromValue> FromSExpr for T {
+	fn from_s_expr(exp : &SExprRef) -> Result<Self, FieldError> {
+		return value_at(exp, 0);
+	}
+}
+
+impl FromSExpr for [f64; 2] {
+	fn from_s_expr(exp : &SExprRef) -> Result<Self, FieldError> {
+		return Ok([value_at(exp, 0)?, value_at(exp, 1)?]);
+	}
+}
+
+impl<T : FromValue> FromSExpr for Vec<T> {
+	fn from_s_expr(exp : &SExprRef) -> Result<Self, FieldError> {
+		return (0..exp.values().len())
+			.map(|i| value_at(exp, i))
+			.collect();
+	}
+}
+
+impl<T : FromSExpr> FromSExpr for Option<T> {
+	fn from_s_expr(exp : &SExprRef) -> Result<Self, FieldError> {
+		return T::from_s_expr(exp).map(Some);
+	}
+
+	fn from_missing(_field : &str) -> Result<Self, FieldError> {
+		return Ok(None);
+	}
+}
+
+
+/// the direct child named name of exp, read as T
+pub fn field<T : FromSExpr>(exp : &SExprRef, name : &str) -> Result<T, FieldError> {
+	return match exp.select(name).first() {
+		Some(child) => T::from_s_expr(child).map_err(|e| e.inside(name)),
+		None => T::from_missing(name),
+	};
+}
+
+
+/// every direct child named name of exp, read as T
+pub fn fields<T : FromSExpr>(exp : &SExprRef, name : &str) -> Result<Vec<T>, FieldError> {
+	return exp.select(name)
+		.iter()
+		.map(|child| T::from_s_expr(child).map_err(|e| e.inside(name)))
+		.collect();
+}
+
+
+/// types that are a single value of an expression when written
+pub trait ToValue {
+	fn to_value(&self) -> Value<'static>;
+}
+
+impl ToValue for f64 {
+	fn to_value(&self) -> Value<'static> {
+		return Value::Float(*self);
+	}
+}
+
+impl ToValue for isize {
+	fn to_value(&self) -> Value<'static> {
+		return Value::Int(*self);
+	}
+}
+
+impl ToValue for usize {
+	fn to_value(&self) -> Value<'static> {
+		return Value::Int(*self as isize);
+	}
+}
+
+impl ToValue for String {
+	fn to_value(&self) -> Value<'static> {
+		return Value::QuotedString(self.clone().into());
+	}
+}
+
+
+/// types that can be written as an expression, the counterpart of FromSExpr
+pub trait ToSExpr {
+	/// appends the values and children of self to exp, which already holds the name
+	fn write_into(&self, exp : &mut SExpr);
+
+	/// if the field is written at all, only empty optional fields are left out
+	fn is_present(&self) -> bool {
+		return true;
+	}
+}
+
+impl<T : ToValue> ToSExpr for T {
+	fn write_into(&self, exp : &mut SExpr) {
+		exp.append_value(self.to_value());
+	}
+}
+
+impl ToSExpr for [f64; 2] {
+	fn write_into(&self, exp : &mut SExpr) {
+		exp.append_value(Value::Float(self[0]));
+		exp.append_value(Value::Float(self[1]));
+	}
+}
+
+impl<T : ToValue> ToSExpr for Vec<T> {
+	fn write_into(&self, exp : &mut SExpr) {
+		for value in self.iter() {
+			exp.append_value(value.to_value());
+		}
+	}
+}
+
+impl<T : ToSExpr> ToSExpr for Option<T> {
+	fn write_into(&self, exp : &mut SExpr) {
+		if let Some(value) = self {
+			value.write_into(exp);
+		}
+	}
+
+	fn is_present(&self) -> bool {
+		return self.is_some();
+	}
+}
+
+
+/// appends (name value) to exp, the counterpart of field
+pub fn write_field<T : ToSExpr>(exp : &mut SExpr, name : &str, value : &T) {
+	if !value.is_present() {
+		return;
+	}
+
+	let mut child = SExpr::named(name);
+	value.write_into(&mut child);
+	exp.append_exp(child);
+}
+
+
+
+/// where one field of a board type is kept in its expression, for s_expr_fields
+pub trait Slot<T> {
+	fn read(&self, exp : &SExprRef) -> Result<T, FieldError>;
+	fn write(&self, exp : &mut SExpr, value : &T);
+}
+
+/// the child (name ...), where most fields are
+pub struct Child(pub &'static str);
+
+impl<T : FromSExpr + ToSExpr> Slot<T> for Child {
+	fn read(&self, exp : &SExprRef) -> Result<T, FieldError> {
+		return field(exp, self.0);
+	}
+
+	fn write(&self, exp : &mut SExpr, value : &T) {
+		write_field(exp, self.0, value);
+	}
+}
+
+/// a child that may be left out, which reads as the default. the default isn't written
+pub struct ChildOrDefault(pub &'static str);
+
+impl<T : FromSExpr + ToSExpr + Default + PartialEq> Slot<T> for ChildOrDefault {
+	fn read(&self, exp : &SExprRef) -> Result<T, FieldError> {
+		return Ok(field::<Option<T>>(exp, self.0)?.unwrap_or_default());
+	}
+
+	fn write(&self, exp : &mut SExpr, value : &T) {
+		if *value != T::default() {
+			write_field(exp, self.0, value);
+		}
+	}
+}
+
+/// every child named name, like the pads of a footprint
+pub struct Children(pub &'static str);
+
+impl<T : FromSExpr + ToSExpr> Slot<Vec<T>> for Children {
+	fn read(&self, exp : &SExprRef) -> Result<Vec<T>, FieldError> {
+		return fields(exp, self.0);
+	}
+
+	fn write(&self, exp : &mut SExpr, value : &Vec<T>) {
+		for x in value.iter() {
+			write_field(exp, self.0, x);
+		}
+	}
+}
+
+/// the value at a position, counting from the first one after the name. values are written
+/// where they come in the table, so they go first and in order
+pub struct Nth(pub usize);
+
+impl<T : FromValue + ToValue> Slot<T> for Nth {
+	fn read(&self, exp : &SExprRef) -> Result<T, FieldError> {
+		return value_at(exp, self.0);
+	}
+
+	fn write(&self, exp : &mut SExpr, value : &T) {
+		exp.append_value(value.to_value());
+	}
+}
+
+/// a value at a position that may be left out, like Nth with the default of ChildOrDefault
+pub struct NthOrDefault(pub usize);
+
+impl<T : FromValue + ToValue + Default + PartialEq> Slot<T> for NthOrDefault {
+	fn read(&self, exp : &SExprRef) -> Result<T, FieldError> {
+		return match exp.values().get(self.0) {
+			Some(_) => value_at(exp, self.0),
+			None => Ok(T::default()),
+		};
+	}
+
+	fn write(&self, exp : &mut SExpr, value : &T) {
+		if *value != T::default() {
+			exp.append_value(value.to_value());
+		}
+	}
+}
+
+/// a field that isn't in the file but worked out after reading, it starts out as the default
+pub struct Derived;
+
+impl<T : Default> Slot<T> for Derived {
+	fn read(&self, _exp : &SExprRef) -> Result<T, FieldError> {
+		return Ok(T::default());
+	}
+
+	fn write(&self, _exp : &mut SExpr, _value : &T) {}
+}
+
+
+/// implements FromSExpr and ToSExpr for a struct from one table of slot => field : Type,
+/// so the type is described once for reading and writing. fields are written in the order
+/// of the table. a then closure gets what was read, to work out the Derived fields
+macro_rules! s_expr_fields {
+	($type:ident {$($slot:expr => $field:ident : $field_type:ty),* $(,)?}) => {
+		s_expr_fields!{$type {$($slot => $field : $field_type),*} then |_ : &mut $type| {}}
+	};
+	($type:ident {$($slot:expr => $field:ident : $field_type:ty),* $(,)?} then $after:expr) => {
+		impl $crate::convert::FromSExpr for $type {
+			fn from_s_expr(exp : &$crate::s_exp_parser::SExprRef) -> Result<Self, $crate::convert::FieldError> {
+				let mut read = $type{
+					$($field : <_ as $crate::convert::Slot<$field_type>>::read(&$slot, exp)?,)*
+				};
+				($after)(&mut read);
+				return Ok(read);
+			}
+		}
+
+		impl $crate::convert::ToSExpr for $type {
+			fn write_into(&self, exp : &mut $crate::s_exp_parser::SExpr) {
+				$(<_ as $crate::convert::Slot<$field_type>>::write(&$slot, exp, &self.$field);)*
+			}
+		}
+	};
+}
+
+pub(crate) use s_expr_fields;
+
+
+#[test]
+fn test_field() {
+	let exp = crate::s_exp_parser::parse("(at 1 2.5 90) (layers \"F.Cu\" \"B.Cu\") (net 3) (width x)").unwrap();
+	let exp = exp.view();
+
+	assert_eq!(field::<[f64; 2]>(&exp, "at"), Ok([1.0, 2.5]));
+	assert_eq!(field::<Vec<String>>(&exp, "layers"), Ok(vec!{"F.Cu".to_string(), "B.Cu".to_string()}));
+	assert_eq!(field::<usize>(&exp, "net"), Ok(3));
+	assert_eq!(field::<Option<usize>>(&exp, "drill"), Ok(None));
+	assert_eq!(field::<Option<usize>>(&exp, "net"), Ok(Some(3)));
+
+	assert_eq!(field::<usize>(&exp, "drill"), Err(FieldError::missing("drill")));
+	assert_eq!(field::<f64>(&exp, "width"), Err(FieldError::invalid("width/0", "a number", &Value::Symbol("x".into()))));
+	assert_eq!(field::<Vec<isize>>(&exp, "layers").unwrap_err().to_string(), "layers/0 should be an integer, found \"F.Cu\"");
+	assert_eq!(field::<[f64; 2]>(&exp, "net").unwrap_err().to_string(), "net/1 is missing");
+}
+
+#[test]
+fn test_write_field() {
+	let mut exp = SExpr::named("via");
+	write_field(&mut exp, "at", &[1.0, 2.5]);
+	write_field(&mut exp, "layers", &vec!{"F.Cu".to_string(), "B.Cu".to_string()});
+	write_field(&mut exp, "net", &3usize);
+	write_field(&mut exp, "drill", &None::<f64>);
+
+	assert_eq!(exp.pretty_print(), "via\n(at 1 2.5)\n(layers \"F.Cu\" \"B.Cu\")\n(net 3)\n");
+
+	let reread = exp.view();
+	assert_eq!(field::<[f64; 2]>(&reread, "at"), Ok([1.0, 2.5]));
+	assert_eq!(field::<Vec<String>>(&reread, "layers"), Ok(vec!{"F.Cu".to_string(), "B.Cu".to_string()}));
+	assert_eq!(field::<Option<f64>>(&reread, "drill"), Ok(None));
+}
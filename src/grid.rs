@@ -0,0 +1,234 @@
+use std::ops::{Index, IndexMut};
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Discrete3D {
+	pub x : usize,
+	pub y : usize,
+	pub layer : usize,
+}
+
+impl Discrete3D {
+	pub fn from(x : usize, y : usize, layer : usize) -> Self {
+		return Self{
+			x : x,
+			y : y,
+			layer : layer,
+		}
+	}
+}
+
+
+/// a value for every cell of an x by y by layers block. cells are stored row after row,
+/// layer after layer, so x is the fastest changing coordinate.
+/// get and get_mut check the bounds, indexing with [] panics outside the grid
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Grid3<T> {
+	data : Vec<T>,
+	x_cells : usize,
+	y_cells : usize,
+	layers : usize,
+}
+
+
+impl<T : Clone> Grid3<T> {
+	pub fn new(x_cells : usize, y_cells : usize, layers : usize, fill : T) -> Self {
+		return Self{
+			data : vec![fill ; x_cells * y_cells * layers],
+			x_cells : x_cells,
+			y_cells : y_cells,
+			layers : layers,
+		};
+	}
+
+	/// sets every cell to value
+	pub fn fill(&mut self, value : T) {
+		self.data.fill(value);
+	}
+}
+
+
+impl<T> Grid3<T> {
+	/// a grid of the same size, with every cell set to fill
+	pub fn same_size<U : Clone>(&self, fill : U) -> Grid3<U> {
+		return Grid3::new(self.x_cells, self.y_cells, self.layers, fill);
+	}
+
+	pub fn x_cells(&self) -> usize {
+		return self.x_cells;
+	}
+
+	pub fn y_cells(&self) -> usize {
+		return self.y_cells;
+	}
+
+	pub fn layers(&self) -> usize {
+		return self.layers;
+	}
+
+	/// the number of cells
+	pub fn len(&self) -> usize {
+		return self.data.len();
+	}
+
+	pub fn is_empty(&self) -> bool {
+		return self.data.is_empty();
+	}
+
+	pub fn contains(&self, point : Discrete3D) -> bool {
+		return point.x < self.x_cells && point.y < self.y_cells && point.layer < self.layers;
+	}
+
+	/// the position of point in the cell list, None outside the grid
+	pub fn index(&self, point : Discrete3D) -> Option<usize> {
+		if !self.contains(point) {
+			return None;
+		}
+		return Some((point.layer * self.y_cells + point.y) * self.x_cells + point.x);
+	}
+
+	/// the cell at index in the cell list, the inverse of index
+	pub fn point(&self, index : usize) -> Discrete3D {
+		let x = index % self.x_cells;
+		let y = (index / self.x_cells) % self.y_cells;
+		let layer = index / (self.x_cells * self.y_cells);
+		return Discrete3D::from(x, y, layer);
+	}
+
+	pub fn get(&self, point : Discrete3D) -> Option<&T> {
+		return self.index(point).map(|i| &self.data[i]);
+	}
+
+	pub fn get_mut(&mut self, point : Discrete3D) -> Option<&mut T> {
+		return self.index(point).map(|i| &mut self.data[i]);
+	}
+
+	/// the values of all cells, in the order of the cell list
+	pub fn iter(&self) -> std::slice::Iter<'_, T> {
+		return self.data.iter();
+	}
+
+	/// every cell of the grid, in the order of the cell list
+	pub fn points(&self) -> impl Iterator<Item = Discrete3D> + '_ {
+		return (0..self.data.len()).map(|i| self.point(i));
+	}
+
+	/// the up to six cells next to point, one step along x, y or the layers
+	pub fn neighbours(&self, point : Discrete3D) -> impl Iterator<Item = Discrete3D> + '_ {
+		let Discrete3D{x, y, layer} = point;
+
+		let steps = [
+			x.checked_sub(1).map(|x| Discrete3D::from(x, y, layer)),
+			y.checked_sub(1).map(|y| Discrete3D::from(x, y, layer)),
+			layer.checked_sub(1).map(|layer| Discrete3D::from(x, y, layer)),
+			Some(Discrete3D::from(x + 1, y, layer)),
+			Some(Discrete3D::from(x, y + 1, layer)),
+			Some(Discrete3D::from(x, y, layer + 1)),
+		];
+
+		return steps.into_iter()
+			.flatten()
+			.filter(|p| self.contains(*p));
+	}
+
+	fn expect_index(&self, point : Discrete3D) -> usize {
+		match self.index(point) {
+			Some(i) => i,
+			None => panic!("{:?} is outside the {} by {} by {} grid", point, self.x_cells, self.y_cells, self.layers),
+		}
+	}
+}
+
+
+impl<T> Index<Discrete3D> for Grid3<T> {
+	type Output = T;
+
+	fn index(&self, point : Discrete3D) -> &T {
+		return &self.data[self.expect_index(point)];
+	}
+}
+
+impl<T> IndexMut<Discrete3D> for Grid3<T> {
+	fn index_mut(&mut self, point : Discrete3D) -> &mut T {
+		let index = self.expect_index(point);
+		return &mut self.data[index];
+	}
+}
+
+// by position in the cell list, for the searches that keep indices around
+impl<T> Index<usize> for Grid3<T> {
+	type Output = T;
+
+	fn index(&self, index : usize) -> &T {
+		return &self.data[index];
+	}
+}
+
+impl<T> IndexMut<usize> for Grid3<T> {
+	fn index_mut(&mut self, index : usize) -> &mut T {
+		return &mut self.data[index];
+	}
+}
+
+
+#[test]
+fn test_grid_index() {
+	let grid = Grid3::new(4, 3, 2, 0);
+
+	// every cell needs its own index, otherwise layers bleed into each other
+	let mut indices : Vec<usize> = grid.points()
+		.map(|p| grid.index(p).unwrap())
+		.collect();
+	indices.sort();
+	indices.dedup();
+	assert_eq!(indices.len(), 4 * 3 * 2);
+
+	let point = Discrete3D::from(3, 2, 1);
+	assert_eq!(grid.index(point), Some(23));
+	assert_eq!(grid.point(23), point);
+	assert_eq!(grid.index(Discrete3D::from(1, 0, 1)), Some(13));
+
+	assert_eq!(grid.index(Discrete3D::from(4, 0, 0)), None);
+	assert_eq!(grid.index(Discrete3D::from(0, 3, 0)), None);
+	assert_eq!(grid.index(Discrete3D::from(0, 0, 2)), None);
+}
+
+#[test]
+fn test_grid_access() {
+	let mut grid = Grid3::new(4, 3, 2, 'a');
+
+	grid[Discrete3D::from(1, 2, 1)] = 'b';
+	*grid.get_mut(Discrete3D::from(0, 0, 0)).unwrap() = 'c';
+
+	assert_eq!(grid.get(Discrete3D::from(1, 2, 1)), Some(&'b'));
+	assert_eq!(grid[Discrete3D::from(1, 2, 0)], 'a');
+	assert_eq!(grid[0], 'c');
+	assert_eq!(grid.get(Discrete3D::from(1, 3, 0)), None);
+	assert!(grid.get_mut(Discrete3D::from(9, 0, 0)).is_none());
+
+	let copy = grid.same_size(0.0);
+	assert_eq!((copy.x_cells(), copy.y_cells(), copy.layers(), copy.len()), (4, 3, 2, 24));
+
+	grid.fill('z');
+	assert!(grid.points().all(|p| grid[p] == 'z'));
+}
+
+#[test]
+#[should_panic]
+fn test_grid_index_out_of_bounds() {
+	let grid = Grid3::new(4, 3, 2, 0);
+	let _ = grid[Discrete3D::from(0, 3, 0)];
+}
+
+#[test]
+fn test_grid_neighbours() {
+	let grid = Grid3::new(4, 3, 2, 0);
+
+	let mut corner : Vec<Discrete3D> = grid.neighbours(Discrete3D::from(0, 0, 0)).collect();
+	corner.sort_by_key(|p| grid.index(*p));
+	assert_eq!(corner, vec!{Discrete3D::from(1, 0, 0), Discrete3D::from(0, 1, 0), Discrete3D::from(0, 0, 1)});
+
+	assert_eq!(grid.neighbours(Discrete3D::from(1, 1, 0)).count(), 5);
+	assert_eq!(grid.neighbours(Discrete3D::from(3, 2, 1)).count(), 3);
+	assert!(grid.neighbours(Discrete3D::from(2, 1, 1)).all(|p| grid.contains(p)));
+}
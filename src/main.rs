@@ -1,34 +1,59 @@
 #![allow(dead_code)]
+#![allow(clippy::needless_return, clippy::redundant_field_names)]
 
 use std::env;
-use std::fs::read_to_string;
+use std::process::exit;
 
 
 mod router;
 mod value;
 mod s_exp_parser;
 mod raster_solver;
+mod convert;
+mod outline;
+mod grid;
 
 fn main() {
 	let mut to_stdout = false;
 
 	let argv : Vec<String> = env::args().collect();
-	if argv.len() < 2 {()} // return if no input and output file is given
+	if argv.len() < 2 {return;} // return if no input and output file is given
 	if argv.len() < 3 {
 		to_stdout = true;
 	}
 
-	let input_file = read_to_string(&argv[1]).unwrap();
-
-	let input_board = s_exp_parser::parse(&input_file).unwrap();
-
-	println!("{:?}", input_board);
-
-	//let output_board = router::route(input_board);
+	let input_board = match router::KicadPcb::from_file(&argv[1]) {
+		Ok(board) => board,
+		Err(e) => {
+			eprintln!("could not read {}: {}", argv[1], e);
+			exit(1);
+		},
+	};
+
+	let settings = router::RouterSettings::default();
+
+	let result = match input_board.route(&settings) {
+		Ok(r) => r,
+		Err(e) => {
+			eprintln!("routing failed: {}", e);
+			exit(1);
+		},
+	};
+
+	for net in result.failed_nets.iter() {
+		eprintln!("could not route net {}", net);
+	}
 
 	if to_stdout {
-//		println!("{:?}", output_board);
-	} else {
-		// write to file
+		match result.board.to_file_string() {
+			Ok(text) => print!("{}", text),
+			Err(e) => {
+				eprintln!("could not write the board: {}", e);
+				exit(1);
+			},
+		}
+	} else if let Err(e) = result.board.write_to_file(&argv[2]) {
+		eprintln!("could not write {}: {}", argv[2], e);
+		exit(1);
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,375 @@
+use std::f64::consts::PI;
+
+use crate::convert::*;
+use crate::router::{KicadPcbError, V2};
+use crate::router::rotate;
+use crate::s_exp_parser::SExprRef;
+use crate::value::value_to_float;
+
+
+/// how far apart two ends may be and still count as joined, in mm
+const JOIN_TOLERANCE : f64 = 1e-3;
+
+/// the largest angle one straight piece of an arc or circle may cover
+const ARC_STEP : f64 = PI / 36.0;
+
+
+// reads the points of one kind of graphic
+type ShapeReader = fn(&SExprRef) -> Result<Vec<V2>, FieldError>;
+
+
+/// the shape of the board, made of the graphics on Edge.Cuts. every polygon is closed,
+/// without repeating the first point at the end. the largest one is the board itself,
+/// the others are cutouts
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Outline {
+	pub polygons : Vec<Vec<V2>>,
+}
+
+
+impl Outline {
+	/// reads the gr_line, gr_arc, gr_circle, gr_rect and gr_poly on Edge.Cuts of a kicad_pcb
+	/// expression, and the fp_ ones of the same kinds inside its footprints. lines and arcs are
+	/// joined end to end, a chain that doesn't close is closed with a straight line
+	pub fn from_exp(exp : &SExprRef) -> Result<Self, KicadPcbError> {
+		let mut polygons : Vec<Vec<V2>> = Vec::new();
+		let mut chains : Vec<Vec<V2>> = Vec::new();
+
+		// the last one says if the shape is closed by itself
+		let kinds : [(&str, ShapeReader, bool); 5] = [
+			("line", read_line, false),
+			("arc", read_arc, false),
+			("circle", read_circle, true),
+			("rect", read_rect, true),
+			("poly", read_poly, true),
+		];
+
+		// the graphics of a footprint are relative to it, like its pads, and turn with it.
+		// ones on B.Cu are stored already mirrored
+		let mut groups = vec!{(*exp, "gr_", [0.0, 0.0], 0.0)};
+		for footprint in exp.select("footprint") {
+			let at = field::<V2>(&footprint, "at").map_err(|e| KicadPcbError::field_fail("footprint", &footprint, e))?;
+			let angle = footprint.value("at/2").and_then(value_to_float).unwrap_or_default();
+			groups.push((footprint, "fp_", at, angle));
+		}
+
+		for (parent, prefix, at, angle) in groups {
+			for (kind, read, closed) in kinds {
+				let kind = format!("{}{}", prefix, kind);
+
+				for shape in parent.select(&kind) {
+					if field::<Option<String>>(&shape, "layer").ok().flatten().as_deref() != Some("Edge.Cuts") {
+						continue;
+					}
+
+					let points : Vec<V2> = match read(&shape) {
+						Ok(x) => x.into_iter()
+							.map(|p| {
+								let offset = rotate(p, angle);
+								[at[0] + offset[0], at[1] + offset[1]]
+							})
+							.collect(),
+						Err(e) => return Err(KicadPcbError::field_fail(&kind, &shape, e)),
+					};
+
+					if closed {
+						polygons.push(points);
+					} else {
+						chains.push(points);
+					}
+				}
+			}
+		}
+
+		polygons.extend(join_chains(chains));
+
+		// largest first, that is the board
+		polygons.sort_by(|a, b| area(b).total_cmp(&area(a)));
+
+		return Ok(Outline{polygons : polygons});
+	}
+
+	pub fn is_empty(&self) -> bool {
+		return self.polygons.is_empty();
+	}
+
+	/// the polygons inside the board, like slots and mounting cutouts
+	pub fn cutouts(&self) -> &[Vec<V2>] {
+		return self.polygons.get(1..).unwrap_or_default();
+	}
+
+	/// if at is on the board, which is inside the outline but not inside a cutout
+	pub fn contains(&self, at : V2) -> bool {
+		// even odd over all polygons, a point in a cutout is inside two of them
+		return self.polygons.iter()
+			.filter(|x| polygon_contains(x, at))
+			.count() % 2 == 1;
+	}
+
+	/// the lowest and highest corner of the rectangle around the board
+	pub fn bounding_box(&self) -> Option<[V2; 2]> {
+		let mut points = self.polygons.iter().flatten();
+		let first = *points.next()?;
+
+		return Some(points.fold([first, first], |[low, high], p| [
+			[low[0].min(p[0]), low[1].min(p[1])],
+			[high[0].max(p[0]), high[1].max(p[1])],
+		]));
+	}
+}
+
+
+pub(crate) fn read_line(exp : &SExprRef) -> Result<Vec<V2>, FieldError> {
+	return Ok(vec!{field(exp, "start")?, field(exp, "end")?});
+}
+
+pub(crate) fn read_rect(exp : &SExprRef) -> Result<Vec<V2>, FieldError> {
+	let start : V2 = field(exp, "start")?;
+	let end : V2 = field(exp, "end")?;
+	return Ok(vec!{start, [end[0], start[1]], end, [start[0], end[1]]});
+}
+
+fn read_circle(exp : &SExprRef) -> Result<Vec<V2>, FieldError> {
+	let center : V2 = field(exp, "center")?;
+	let end : V2 = field(exp, "end")?;
+	let radius = (end[0] - center[0]).hypot(end[1] - center[1]);
+
+	let steps = (2.0 * PI / ARC_STEP).ceil() as usize;
+	return Ok((0..steps)
+		.map(|i| point_on(center, radius, 2.0 * PI * i as f64 / steps as f64))
+		.collect());
+}
+
+pub(crate) fn read_poly(exp : &SExprRef) -> Result<Vec<V2>, FieldError> {
+	return match exp.select("pts").first() {
+		Some(pts) => fields(pts, "xy"),
+		None => Err(FieldError::missing("pts")),
+	};
+}
+
+// arcs are three points on the circle, (start) (mid) (end). older files have the centre
+// in start, the first point in end and the angle it spans in degrees
+pub(crate) fn read_arc(exp : &SExprRef) -> Result<Vec<V2>, FieldError> {
+	let start : V2 = field(exp, "start")?;
+	let end : V2 = field(exp, "end")?;
+
+	let (center, from, sweep) = match field::<Option<V2>>(exp, "mid")? {
+		Some(mid) => {
+			let center = match circle_center(start, mid, end) {
+				Some(c) => c,
+				None => return Ok(vec!{start, end}), // all three in a line
+			};
+			let from = angle_of(center, start);
+			let ccw = (angle_of(center, end) - from).rem_euclid(2.0 * PI);
+			let through_mid = (angle_of(center, mid) - from).rem_euclid(2.0 * PI) < ccw;
+			(center, from, if through_mid {ccw} else {ccw - 2.0 * PI})
+		},
+		None => {
+			// y points down, so a positive angle in the file turns the other way here
+			let angle : f64 = field(exp, "angle")?;
+			(start, angle_of(start, end), -angle.to_radians())
+		},
+	};
+
+	let radius = (end[0] - center[0]).hypot(end[1] - center[1]);
+	let steps = ((sweep.abs() / ARC_STEP).ceil() as usize).max(1);
+
+	let mut points : Vec<V2> = (0..=steps)
+		.map(|i| point_on(center, radius, from + sweep * i as f64 / steps as f64))
+		.collect();
+
+	// the ends exactly as written, so they join the neighbouring lines
+	if field::<Option<V2>>(exp, "mid")?.is_some() {
+		points[0] = start;
+		points[steps] = end;
+	} else {
+		points[0] = end;
+	}
+
+	return Ok(points);
+}
+
+
+// angle of p around center. the math is done with y up, so screen and file agree
+// on what counterclockwise means
+fn angle_of(center : V2, p : V2) -> f64 {
+	return (-(p[1] - center[1])).atan2(p[0] - center[0]);
+}
+
+fn point_on(center : V2, radius : f64, angle : f64) -> V2 {
+	return [center[0] + radius * angle.cos(), center[1] - radius * angle.sin()];
+}
+
+// centre of the circle through three points, None if they are in a line
+fn circle_center(a : V2, b : V2, c : V2) -> Option<V2> {
+	let d = 2.0 * (a[0] * (b[1] - c[1]) + b[0] * (c[1] - a[1]) + c[0] * (a[1] - b[1]));
+	if d.abs() < 1e-12 {
+		return None;
+	}
+
+	let a2 = a[0] * a[0] + a[1] * a[1];
+	let b2 = b[0] * b[0] + b[1] * b[1];
+	let c2 = c[0] * c[0] + c[1] * c[1];
+
+	return Some([
+		(a2 * (b[1] - c[1]) + b2 * (c[1] - a[1]) + c2 * (a[1] - b[1])) / d,
+		(a2 * (c[0] - b[0]) + b2 * (a[0] - c[0]) + c2 * (b[0] - a[0])) / d,
+	]);
+}
+
+
+fn near(a : V2, b : V2) -> bool {
+	return (a[0] - b[0]).hypot(a[1] - b[1]) <= JOIN_TOLERANCE;
+}
+
+// joins lines and arcs that share ends into closed polygons
+fn join_chains(mut pieces : Vec<Vec<V2>>) -> Vec<Vec<V2>> {
+	let mut polygons = Vec::new();
+
+	while let Some(mut chain) = pieces.pop() {
+		loop {
+			let (first, last) = (chain[0], chain[chain.len() - 1]);
+			if chain.len() > 2 && near(first, last) {
+				break;
+			}
+
+			// a piece that continues the chain, either way round
+			let next = pieces.iter().position(|x| near(x[0], last) || near(x[x.len() - 1], last));
+			let mut piece = match next {
+				Some(i) => pieces.swap_remove(i),
+				None => break, // left open, closed by the polygon itself
+			};
+
+			if !near(piece[0], last) {
+				piece.reverse();
+			}
+			chain.extend(piece.into_iter().skip(1));
+		}
+
+		if chain.len() > 2 && near(chain[0], chain[chain.len() - 1]) {
+			chain.pop();
+		}
+
+		if chain.len() > 2 {
+			polygons.push(chain);
+		}
+	}
+
+	return polygons;
+}
+
+
+// area of a polygon, without the sign that says which way round it goes
+fn area(polygon : &[V2]) -> f64 {
+	let twice : f64 = (0..polygon.len())
+		.map(|i| {
+			let a = polygon[i];
+			let b = polygon[(i + 1) % polygon.len()];
+			a[0] * b[1] - b[0] * a[1]
+		})
+		.sum();
+
+	return twice.abs() / 2.0;
+}
+
+// crossing number test, points on the edge may go either way
+pub(crate) fn polygon_contains(polygon : &[V2], at : V2) -> bool {
+	let mut inside = false;
+
+	for i in 0..polygon.len() {
+		let a = polygon[i];
+		let b = polygon[(i + 1) % polygon.len()];
+
+		if (a[1] > at[1]) != (b[1] > at[1]) {
+			let x = a[0] + (at[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0]);
+			if at[0] < x {
+				inside = !inside;
+			}
+		}
+	}
+
+	return inside;
+}
+
+
+#[test]
+fn test_outline_from_exp() {
+	let test_string = "(kicad_pcb
+		(gr_line (start 0 0) (end 30 0) (layer \"Edge.Cuts\") (width 0.1))
+		(gr_line (start 30 0) (end 30 20) (layer \"Edge.Cuts\") (width 0.1))
+		(gr_arc (start 30 20) (mid 15 35) (end 0 20) (layer \"Edge.Cuts\") (width 0.1))
+		(gr_line (start 0 0) (end 0 20) (layer \"Edge.Cuts\") (width 0.1))
+		(gr_circle (center 5 5) (end 6 5) (layer \"Edge.Cuts\") (width 0.1))
+		(gr_rect (start 20 5) (end 25 10) (layer \"Edge.Cuts\") (width 0.1))
+		(gr_line (start -10 -10) (end 100 100) (layer \"F.SilkS\") (width 0.1)))";
+
+	let exp = crate::s_exp_parser::parse(test_string).unwrap();
+	let outline = Outline::from_exp(&exp.remove_trivial()).unwrap();
+
+	assert_eq!(outline.polygons.len(), 3);
+	assert_eq!(outline.cutouts().len(), 2);
+
+	let [low, high] = outline.bounding_box().unwrap();
+	assert_eq!(low, [0.0, 0.0]);
+	assert_eq!(high[0], 30.0);
+	assert!((high[1] - 35.0).abs() < 1e-9);
+
+	assert!(outline.contains([15.0, 10.0]));
+	assert!(outline.contains([15.0, 30.0])); // under the arc
+	assert!(!outline.contains([1.0, 30.0])); // outside the arc
+	assert!(!outline.contains([5.5, 5.0])); // in the round cutout
+	assert!(!outline.contains([22.0, 7.0])); // in the square one
+	assert!(!outline.contains([-1.0, 10.0]));
+	assert!(!outline.contains([31.0, 10.0]));
+}
+
+#[test]
+fn test_outline_footprints() {
+	// a board with a slot drawn by a footprint turned by 90 degrees, so the slot is upright
+	let test_string = "(kicad_pcb
+		(gr_rect (start 0 0) (end 30 20) (layer \"Edge.Cuts\") (width 0.1))
+		(footprint \"Slot\" (layer \"F.Cu\") (at 10 10 90)
+			(fp_line (start -3 -1) (end 3 -1) (layer \"Edge.Cuts\") (width 0.1))
+			(fp_arc (start 3 -1) (mid 4 0) (end 3 1) (layer \"Edge.Cuts\") (width 0.1))
+			(fp_line (start 3 1) (end -3 1) (layer \"Edge.Cuts\") (width 0.1))
+			(fp_line (start -3 1) (end -3 -1) (layer \"Edge.Cuts\") (width 0.1))
+			(fp_circle (center 0 0) (end 1 0) (layer \"F.SilkS\") (width 0.1)))
+		(footprint \"Hole\" (layer \"F.Cu\") (at 25 5)
+			(fp_circle (center 0 0) (end 1 0) (layer \"Edge.Cuts\") (width 0.1))))";
+
+	let exp = crate::s_exp_parser::parse(test_string).unwrap();
+	let outline = Outline::from_exp(&exp.remove_trivial()).unwrap();
+
+	assert_eq!(outline.polygons.len(), 3);
+	assert!(outline.contains([5.0, 5.0]));
+	assert!(!outline.contains([10.0, 7.5])); // in the slot, above the footprint
+	assert!(!outline.contains([10.0, 6.5])); // under the arc at its top end
+	assert!(outline.contains([12.5, 10.0])); // where the slot would be without the turn
+	assert!(!outline.contains([25.5, 5.0])); // in the hole
+	assert!(outline.contains([27.0, 5.0]));
+}
+
+#[test]
+fn test_outline_arcs() {
+	// the old form, centre, first point and angle. 90 degrees from the right to the top
+	let exp = crate::s_exp_parser::parse("(gr_arc (start 0 0) (end 10 0) (angle -90) (layer \"Edge.Cuts\"))").unwrap();
+	let points = read_arc(&exp.select("gr_arc")[0]).unwrap();
+
+	assert_eq!(points[0], [10.0, 0.0]);
+	let last = points[points.len() - 1];
+	assert!(last[0].abs() < 1e-9 && (last[1] + 10.0).abs() < 1e-9);
+
+	// three points clockwise on screen, the arc has to pass through the middle one
+	let exp = crate::s_exp_parser::parse("(gr_arc (start 10 0) (mid 0 10) (end -10 0) (layer \"Edge.Cuts\"))").unwrap();
+	let points = read_arc(&exp.select("gr_arc")[0]).unwrap();
+	assert!(points.iter().all(|p| p[1] >= -1e-9));
+	assert!(points.iter().any(|p| near(*p, [0.0, 10.0])));
+}
+
+#[test]
+fn test_outline_errors() {
+	let exp = crate::s_exp_parser::parse("(kicad_pcb (gr_line (start 0 0) (layer \"Edge.Cuts\") (tstamp 3ba3cee6-48b9-48e2-a466-821ec0c8a1c3)))").unwrap();
+	let error = Outline::from_exp(&exp.remove_trivial()).unwrap_err();
+
+	assert_eq!(error.to_string(), "could not read gr_line 3ba3cee6-48b9-48e2-a466-821ec0c8a1c3: end is missing");
+}
@@ -1,116 +1,1165 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::collections::BinaryHeap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+use crate::grid::{Discrete3D, Grid3};
+use crate::outline::Outline;
+use crate::router::Algorithm;
 use crate::router::KicadPcb;
+use crate::router::KicadPcbError;
+use crate::router::new_tstamp;
+use crate::router::NetId;
+use crate::router::Pad;
+use crate::router::RouterSettings;
 use crate::router::V2;
+use crate::router::Via;
+use crate::router::Wire;
 
-#[derive(Debug, Clone, Copy, Default)]
-struct Discrete3D {
-	pub x : usize,
-	pub y : usize,
-	pub layer : usize,
+/// costs used by the a* search. even layers prefer horizontal wires, odd layers vertical ones,
+/// so with two layers F.Cu runs horizontal and B.Cu vertical
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostModel {
+	pub step : f64,
+	pub via : f64,
+	pub wrong_direction : f64, // added to step when going against the layers preferred direction
+	pub obstacle_proximity : f64, // added when entering a cell next to something the net can't use
 }
 
-impl Discrete3D {
-	pub fn from(x : usize, y : usize, layer : usize) -> Self {
+impl Default for CostModel {
+	fn default() -> Self {
 		return Self{
-			x : x,
-			y : y,
-			layer : layer,
+			step : 1.0,
+			via : 10.0,
+			wrong_direction : 1.0,
+			obstacle_proximity : 2.0,
+		};
+	}
+}
+
+impl CostModel {
+	/// cost of moving between two neighbouring cells, not counting obstacle proximity
+	fn move_cost(&self, from : Discrete3D, to : Discrete3D) -> f64 {
+		if from.layer != to.layer {
+			return self.via;
+		}
+
+		let horizontal = from.y == to.y;
+		let prefers_horizontal = from.layer.is_multiple_of(2);
+
+		if horizontal == prefers_horizontal {
+			return self.step;
 		}
+		return self.step + self.wrong_direction;
+	}
+
+	/// lower bound for the cost from a to b
+	fn estimate(&self, a : Discrete3D, b : Discrete3D) -> f64 {
+		let planar = a.x.abs_diff(b.x) + a.y.abs_diff(b.y);
+		let vertical = a.layer.abs_diff(b.layer);
+		return planar as f64 * self.step + vertical as f64 * self.via;
+	}
+}
+
+
+/// open list entry of the a* search, ordered so the binary heap pops the cheapest first
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Candidate {
+	estimate : f64,
+	index : usize,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+	fn cmp(&self, other : &Self) -> Ordering {
+		other.estimate.total_cmp(&self.estimate)
+			.then_with(|| other.index.cmp(&self.index))
+	}
+}
+
+impl PartialOrd for Candidate {
+	fn partial_cmp(&self, other : &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RouteReport {
+	pub routed : Vec<NetId>,
+	pub failed : Vec<NetId>,
+	pub passes : usize, // rip-up and reroute passes that were needed
+	pub congestion : Vec<CongestionStats>, // one entry per negotiated congestion iteration
+}
+
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CongestionStats {
+	pub iteration : usize,
+	pub overused_cells : usize, // cells used by more than one net
+	pub overuse : usize, // sum over all cells of the nets in excess of one
+}
+
+
+/// parameters of the negotiated congestion router
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NegotiationSettings {
+	pub iterations : usize,
+	pub costs : CostModel,
+	pub history_gain : f64, // added to a cells history cost per excess net and iteration
+	pub present_start : f64, // penalty factor per other net on a cell in the first iteration
+	pub present_growth : f64, // the present factor is multiplied by this every iteration
+}
+
+impl Default for NegotiationSettings {
+	fn default() -> Self {
+		return Self{
+			iterations : 30,
+			costs : CostModel::default(),
+			history_gain : 1.0,
+			present_start : 0.5,
+			present_growth : 1.5,
+		};
 	}
 }
 
 
+/// how far below a cell corner, in cells, a position may be and still count as that corner
+const CELL_EPSILON : f64 = 1e-6;
+
+// x rounded to the nanometres kicad stores
+fn to_nanometres(x : f64) -> f64 {
+	return (x * 1e6).round() / 1e6;
+}
+
+
 #[derive(Debug, Default, Clone)]
-struct Raster {
-	data : Vec<GridState>,
-	x_cells : usize,
-	y_cells : usize,
-	layers : usize,
+pub struct Raster {
+	data : Grid3<GridState>,
+	owners : Grid3<Option<NetId>>, // net occupying each cell, if any
+	terminals : BTreeMap<NetId, Vec<Vec<Discrete3D>>>, // every terminal is a group of cells
+	routes : BTreeMap<NetId, Vec<Vec<Discrete3D>>>, // paths laid down by the router
+	keepout : Grid3<Vec<NetId>>, // nets whose copper is too close to the cell for tracks of other nets
 	spacing : f64,
+	origin : V2, // board position of the corner of cell 0, 0
+	track_width : f64, // of the tracks the router lays down, in mm
+	via_size : f64,
+	clearance : f64,
 }
 
 
 impl Raster {
-	pub fn new(board_params : &KicadPcb) -> Self {
+	pub fn new(board_params : &KicadPcb, settings : &RouterSettings) -> Self {
 		//decide grid spacing
 		let spacing : f64 = 0.1; //no!!
 
-		let x_size : f64 = 10.0; //pcb needs to contain this info
-		let y_size : f64= 10.0;
+		// the grid covers the board, lined up with multiples of the spacing so existing
+		// tracks on the usual kicad grids land on cell corners
+		let [low, high] = board_params.bounding_box().unwrap_or([[0.0, 0.0], [10.0, 10.0]]);
+		let origin = [
+			(low[0] / spacing + CELL_EPSILON).floor() * spacing,
+			(low[1] / spacing + CELL_EPSILON).floor() * spacing,
+		];
 
 		//init grid
-		let x = (x_size / spacing).ceil() as usize;
-		let y = (y_size / spacing).ceil() as usize;
+		let x = ((high[0] - origin[0]) / spacing - CELL_EPSILON).ceil().max(1.0) as usize;
+		let y = ((high[1] - origin[1]) / spacing - CELL_EPSILON).ceil().max(1.0) as usize;
 		let z = board_params.routable_layers();
 
-		let mut raster = Self{
-			data : vec![GridState::Free ; x * y * z],
-			x_cells : x,
-			y_cells : y,
-			layers : z,
-			spacing : spacing,
-		};
+		let mut raster = Self::blank(x, y, z, spacing);
+		raster.origin = origin;
+		raster.track_width = settings.track_width;
+		raster.via_size = settings.via_size;
+		raster.clearance = settings.clearance;
+
+		// how far the middle of a new track has to stay from the copper of other nets
+		let track_reach = settings.clearance + settings.track_width / 2.0;
 
+		if !board_params.outline.is_empty() {
+			raster.block_outside(&board_params.outline);
+		}
 
 		//put pads and vias and existing wires down
-		for pad in board_params
-			.footprints
-			.iter()
-			.flat_map(|x| x.pads.iter()) 
-		{
-			//TODO: place pads of actual size instead of one single cell
-			let point = raster.get_discrete(pad.abs_at, 1);
-			raster.set(point, GridState::Pad);
+		for (net, items) in board_params.net_index() {
+			for pad in items.pads.iter() {
+				// through hole pads are on every copper layer, smd pads only on their side
+				let layers = board_params.copper_layer_indices(&board_params.layer_set(&pad.layer));
+				let cells : Vec<Discrete3D> = layers.iter()
+					.flat_map(|layer| raster.pad_cells(pad, *layer, 0.0))
+					.collect();
+				if cells.is_empty() {
+					continue;
+				}
+				for point in cells.iter() {
+					raster.occupy(*point, GridState::Pad, net);
+				}
+
+				let margin = pad.clearance.unwrap_or(settings.clearance) + settings.track_width / 2.0;
+				let halo : Vec<Discrete3D> = layers.iter()
+					.flat_map(|layer| raster.pad_cells(pad, *layer, margin))
+					.collect();
+				raster.keep_out(net, &halo);
+
+				raster.add_terminal(net, cells);
+			}
 		}
 
 		for via in board_params.vias.iter() {
-			for layer in via.layers.iter() {
-				let point = raster.get_discrete(via.at, board_params.get_layer_id(&layer).unwrap());
-				raster.set(point, GridState::Via);
+			// a via names the layers it starts and ends on and goes through everything between
+			let layers = board_params.copper_layer_indices(&board_params.layer_set(&via.layers));
+			let (first, last) = match (layers.first(), layers.last()) {
+				(Some(first), Some(last)) => (*first, *last),
+				_ => continue,
+			};
+
+			for layer_index in first..=last {
+				if let Some(point) = raster.get_discrete(via.at, layer_index) {
+					raster.occupy(point, GridState::UserVia, via.net_id);
+				}
+				let halo = raster.cells_near(via.at, via.at, via.size / 2.0 + track_reach, layer_index);
+				raster.keep_out(via.net_id, &halo);
 			}
 		}
 
 		for wire in board_params.wires.iter() {
-			todo!();
+			let layer_index = match board_params.copper_layer_index(&wire.layer_name) {
+				Some(l) => l,
+				None => continue,
+			};
+
+			// walk along the wire in half cell steps so no cell is skipped
+			let dx = wire.end[0] - wire.start[0];
+			let dy = wire.end[1] - wire.start[1];
+			let steps = ((dx.hypot(dy) / (spacing / 2.0)).ceil() as usize).max(1);
+
+			for step in 0..=steps {
+				let t = step as f64 / steps as f64;
+				let at = [wire.start[0] + t * dx, wire.start[1] + t * dy];
+				if let Some(point) = raster.get_discrete(at, layer_index) {
+					if raster.get(point) == Some(GridState::Free) {
+						raster.occupy(point, GridState::UserWire, wire.net_id);
+					}
+				}
+			}
+
+			let halo = raster.cells_near(wire.start, wire.end, wire.width / 2.0 + track_reach, layer_index);
+			raster.keep_out(wire.net_id, &halo);
+		}
+
+		raster.join_connected_terminals();
+
+		return raster;
+	}
+
+	/// joins the terminals of each net that existing wires and vias already connect. the copper
+	/// in between becomes part of the joined terminal, so new paths may also end on it
+	fn join_connected_terminals(&mut self) {
+		let nets : Vec<NetId> = self.terminals.keys().copied().collect();
+
+		for net in nets {
+			let terminals = self.terminals.remove(&net).unwrap_or_default();
+			let mut joined : Vec<Vec<Discrete3D>> = Vec::new();
+			let mut seen : HashSet<Discrete3D> = HashSet::new();
+
+			for terminal in terminals {
+				// reached through the copper of an earlier one
+				if terminal.iter().any(|x| seen.contains(x)) {
+					continue;
+				}
+				joined.push(self.connected_copper(net, terminal, &mut seen));
+			}
+
+			self.terminals.insert(net, joined);
+		}
+	}
+
+	/// the copper of net that is connected to the start cells. wires are rasterized cell by cell,
+	/// so diagonal neighbours touch too. layers are only joined by vias and through hole pads
+	fn connected_copper(&self, net : NetId, start : Vec<Discrete3D>, seen : &mut HashSet<Discrete3D>) -> Vec<Discrete3D> {
+		let copper = |p : Discrete3D| self.owner(p) == Some(net)
+			&& matches!(self.get(p), Some(GridState::Pad | GridState::UserWire | GridState::UserVia));
+		let joins_layers = |p : Discrete3D| matches!(self.get(p), Some(GridState::Pad | GridState::UserVia));
+
+		let mut cells = Vec::new();
+		seen.extend(start.iter().copied());
+		let mut queue : VecDeque<Discrete3D> = start.into_iter().collect();
+
+		while let Some(p) = queue.pop_front() {
+			cells.push(p);
+
+			let in_plane = (-1..=1isize)
+				.flat_map(|dy| (-1..=1isize).map(move |dx| (dx, dy)))
+				.filter_map(|(dx, dy)| Some(Discrete3D::from(
+					p.x.checked_add_signed(dx)?,
+					p.y.checked_add_signed(dy)?,
+					p.layer,
+				)));
+			let through = self.neighbours(p)
+				.filter(|x| x.layer != p.layer && joins_layers(p) && joins_layers(*x));
+
+			for next in in_plane.chain(through).collect::<Vec<Discrete3D>>() {
+				if self.contains(next) && copper(next) && seen.insert(next) {
+					queue.push_back(next);
+				}
+			}
 		}
 
+		return cells;
+	}
 
-		return Self::default();
+	/// an empty raster of the given dimensions, without any board content. its tracks and vias
+	/// have no width and need no clearance, so only the cells themselves are in the way
+	pub fn blank(x_cells : usize, y_cells : usize, layers : usize, spacing : f64) -> Self {
+		return Self{
+			data : Grid3::new(x_cells, y_cells, layers, GridState::Free),
+			owners : Grid3::new(x_cells, y_cells, layers, None),
+			terminals : BTreeMap::new(),
+			routes : BTreeMap::new(),
+			keepout : Grid3::new(x_cells, y_cells, layers, Vec::new()),
+			spacing : spacing,
+			origin : [0.0, 0.0],
+			track_width : 0.0,
+			via_size : 0.0,
+			clearance : 0.0,
+		};
 	}
 
-	pub fn get(&self, pos : Discrete3D) -> GridState {
-		return *self.data.get(self.index(pos)).unwrap();
+	/// the state of a cell, None outside the raster
+	pub fn get(&self, pos : Discrete3D) -> Option<GridState> {
+		return self.data.get(pos).copied();
 	}
 
+	/// sets the state of a cell, which has to be inside the raster
 	pub fn set(&mut self, pos : Discrete3D, value : GridState) {
-		let index = self.index(pos);
-		self.data[index] = value;
+		self.data[pos] = value;
+	}
+
+	pub fn owner(&self, pos : Discrete3D) -> Option<NetId> {
+		return self.owners.get(pos).copied().flatten();
+	}
+
+	/// sets the state of a cell and marks it as belonging to net
+	pub fn occupy(&mut self, pos : Discrete3D, value : GridState, net : NetId) {
+		self.data[pos] = value;
+		self.owners[pos] = Some(net);
+	}
+
+	/// keeps the tracks of nets other than net out of cells
+	fn keep_out(&mut self, net : NetId, cells : &[Discrete3D]) {
+		for cell in cells.iter() {
+			self.keepout[*cell].push(net);
+		}
+	}
+
+	/// undoes keep_out with the same cells
+	fn release(&mut self, net : NetId, cells : &[Discrete3D]) {
+		for cell in cells.iter() {
+			let nets = &mut self.keepout[*cell];
+			if let Some(i) = nets.iter().position(|x| *x == net) {
+				nets.swap_remove(i);
+			}
+		}
+	}
+
+	/// registers a group of cells, which all need to be connected to the rest of net
+	pub fn add_terminal(&mut self, net : NetId, cells : Vec<Discrete3D>) {
+		self.terminals.entry(net).or_default().push(cells);
 	}
 
+	/// the paths the router laid down for net, each from the existing tree to a new terminal
+	pub fn routes(&self, net : NetId) -> &[Vec<Discrete3D>] {
+		return match self.routes.get(&net) {
+			Some(paths) => paths,
+			None => &[],
+		};
+	}
+
+	pub fn contains(&self, point : Discrete3D) -> bool {
+		return self.data.contains(point);
+	}
+
+	// position of a cell in the lists the searches keep, point has to be inside the raster
 	fn index(&self, point : Discrete3D) -> usize {
-		return self.layers * point.layer + self.x_cells * point.x + point.y;
+		return match self.data.index(point) {
+			Some(i) => i,
+			None => panic!("{:?} is outside the raster", point),
+		};
+	}
+
+	fn point(&self, index : usize) -> Discrete3D {
+		return self.data.point(index);
+	}
+
+	/// the cell at board position at, None if that is outside the raster. a cell reaches
+	/// from its corner up to, but not including, the corner of the next one
+	fn get_discrete(&self, at : V2, layer : usize) -> Option<Discrete3D> {
+		let x = usize::try_from(self.cell_index(at[0], 0)).ok()?;
+		let y = usize::try_from(self.cell_index(at[1], 1)).ok()?;
+		let point = Discrete3D::from(x, y, layer);
+
+		return if self.contains(point) {Some(point)} else {None};
+	}
+
+	// the cell along axis that holds at, can be negative or past the end. positions a hair
+	// below a corner, as 0.3 / 0.1 comes out, still count as that corner
+	fn cell_index(&self, at : f64, axis : usize) -> isize {
+		return ((at - self.origin[axis]) / self.spacing + CELL_EPSILON).floor() as isize;
+	}
+
+	/// board coordinates of the corner of a cell, the inverse of get_discrete
+	fn get_continuous(&self, point : Discrete3D) -> V2 {
+		return [
+			to_nanometres(self.origin[0] + point.x as f64 * self.spacing),
+			to_nanometres(self.origin[1] + point.y as f64 * self.spacing),
+		];
+	}
+
+	/// board coordinates of the middle of a cell. pads are rasterized by the cells whose middle
+	/// they cover, so this is where new tracks go
+	fn cell_centre(&self, point : Discrete3D) -> V2 {
+		return [
+			to_nanometres(self.origin[0] + (point.x as f64 + 0.5) * self.spacing),
+			to_nanometres(self.origin[1] + (point.y as f64 + 0.5) * self.spacing),
+		];
+	}
+
+	/// the cells on layer of the rectangle from low to high, cut off at the edges of the raster
+	fn cells_between(&self, low : V2, high : V2, layer : usize) -> Vec<Discrete3D> {
+		let range = |axis : usize, cells : usize| {
+			let first = self.cell_index(low[axis], axis).max(0);
+			let last = self.cell_index(high[axis], axis).min(cells as isize - 1);
+			return first..last + 1;
+		};
+
+		let mut cells = Vec::new();
+		for y in range(1, self.data.y_cells()) {
+			for x in range(0, self.data.x_cells()) {
+				cells.push(Discrete3D::from(x as usize, y as usize, layer));
+			}
+		}
+
+		return cells;
+	}
+
+	/// marks every cell whose centre is off the board as Blocked, on all layers
+	fn block_outside(&mut self, outline : &Outline) {
+		for y in 0..self.data.y_cells() {
+			for x in 0..self.data.x_cells() {
+				if outline.contains(self.cell_centre(Discrete3D::from(x, y, 0))) {
+					continue;
+				}
+
+				for layer in 0..self.data.layers() {
+					self.set(Discrete3D::from(x, y, layer), GridState::Blocked);
+				}
+			}
+		}
+	}
+
+	/// the cells on layer whose centres are covered by the copper of pad, grown by margin.
+	/// the cell under the centre of the pad is always part of it, so pads smaller than a cell
+	/// don't vanish
+	fn pad_cells(&self, pad : &Pad, layer : usize, margin : f64) -> Vec<Discrete3D> {
+		let reach = pad.reach() + margin;
+		let low = [pad.abs_at[0] - reach, pad.abs_at[1] - reach];
+		let high = [pad.abs_at[0] + reach, pad.abs_at[1] + reach];
+		let centre = self.get_discrete(pad.abs_at, layer);
+
+		return self.cells_between(low, high, layer)
+			.into_iter()
+			.filter(|x| Some(*x) == centre || pad.covers(self.cell_centre(*x), margin))
+			.collect();
+	}
+
+	/// the cells on layer whose centres are closer than reach to the line from start to end
+	fn cells_near(&self, start : V2, end : V2, reach : f64, layer : usize) -> Vec<Discrete3D> {
+		let low = [start[0].min(end[0]) - reach, start[1].min(end[1]) - reach];
+		let high = [start[0].max(end[0]) + reach, start[1].max(end[1]) + reach];
+
+		return self.cells_between(low, high, layer)
+			.into_iter()
+			.filter(|x| segment_distance(self.cell_centre(*x), start, end) < reach)
+			.collect();
+	}
+
+	fn neighbours(&self, point : Discrete3D) -> impl Iterator<Item = Discrete3D> + '_ {
+		return self.data.neighbours(point);
+	}
+
+	/// whether net may put a wire through the cell. free cells too close to the copper of
+	/// another net are not passable
+	fn passable(&self, pos : Discrete3D, net : NetId) -> bool {
+		if self.owners[pos] == Some(net) {
+			return true;
+		}
+		return self.data[pos] == GridState::Free && self.keepout[pos].iter().all(|x| *x == net);
+	}
+
+	/// whether net may go from a cell to its neighbour. a layer change puts a via down,
+	/// which needs room for all of its copper on both layers
+	fn can_step(&self, from : Discrete3D, to : Discrete3D, net : NetId) -> bool {
+		if !self.passable(to, net) {
+			return false;
+		}
+		return from.layer == to.layer || (self.via_fits(from, net) && self.via_fits(to, net));
+	}
+
+	fn via_fits(&self, at : Discrete3D, net : NetId) -> bool {
+		// passable cells are already a track away from everything else
+		let centre = self.cell_centre(at);
+		return self.cells_near(centre, centre, (self.via_size - self.track_width) / 2.0, at.layer)
+			.into_iter()
+			.all(|x| self.passable(x, net));
+	}
+
+	/// the cells that other nets have to stay out of around a routed path. only the part of
+	/// that distance given by share is taken
+	fn around_path(&self, path : &[Discrete3D], share : f64) -> Vec<Discrete3D> {
+		let mut cells = Vec::new();
+
+		for (i, cell) in path.iter().enumerate() {
+			// to the middle of a track of another net
+			let reach = match changes_layer(path, i) {
+				true => self.via_size / 2.0 + self.clearance + self.track_width / 2.0,
+				false => self.track_width + self.clearance,
+			};
+			let centre = self.cell_centre(*cell);
+			cells.extend(self.cells_near(centre, centre, reach * share, cell.layer));
+		}
+
+		cells.sort_by_key(|x| self.index(*x));
+		cells.dedup();
+		return cells;
 	}
 
-	fn get_discrete(&self, at : V2, layer : usize) -> Discrete3D {
-		let x = (at[0] / self.spacing) as usize;
-		let y = (at[1] / self.spacing) as usize;
-		return Discrete3D::from(x, y, layer);
+	/// whether any in plane neighbour of the cell is blocked for net
+	fn near_obstacle(&self, pos : Discrete3D, net : NetId) -> bool {
+		return self.neighbours(pos)
+			.filter(|p| p.layer == pos.layer)
+			.any(|p| !self.passable(p, net));
 	}
 
-	pub fn route(&mut self) {
+	/// routes every net with more than one terminal and reports which ones could not be connected.
+	/// failed nets get up to settings.max_passes rounds of rip-up and reroute
+	pub fn route(&mut self, settings : &RouterSettings) -> RouteReport {
 		//http://www.eecs.northwestern.edu/~haizhou/357/lec6.pdf
-		todo!();
+		let mut report = RouteReport::default();
+
+		// net 0 is kicads "not connected"
+		let nets : Vec<NetId> = self.terminals.keys()
+			.copied()
+			.filter(|n| *n != 0)
+			.collect();
+
+		let mut failed : Vec<NetId> = match &settings.algorithm {
+			Algorithm::Negotiated(negotiation) => self.negotiate(&nets, negotiation, &mut report),
+			_ => nets.iter()
+				.copied()
+				.filter(|n| !self.route_net(*n, &settings.algorithm))
+				.collect(),
+		};
+
+		while !failed.is_empty() && report.passes < settings.max_passes {
+			report.passes += 1;
+			failed = self.rip_up_and_reroute(&failed, &settings.algorithm);
+		}
+
+		report.routed = nets.iter()
+			.copied()
+			.filter(|n| !failed.contains(n))
+			.collect();
+		report.failed = failed;
+
+		return report;
+	}
+
+	/// one rip-up and reroute pass. for every failed net the routed nets in its way are removed,
+	/// the failed net is routed first and the removed ones are requeued behind it.
+	/// returns the nets that are still unrouted after the pass
+	fn rip_up_and_reroute(&mut self, failed : &[NetId], algorithm : &Algorithm) -> Vec<NetId> {
+		let mut still_failed : Vec<NetId> = Vec::new();
+
+		for net in failed.iter() {
+			// an earlier net in this pass may have been rerouted in a way that frees this one
+			if self.route_net(*net, algorithm) {
+				continue;
+			}
+
+			let victims = self.blocking_nets(*net);
+			if victims.is_empty() {
+				// blocked by something that can't be moved, like pads or user wires
+				still_failed.push(*net);
+				continue;
+			}
+
+			for victim in victims.iter() {
+				self.rip_up(*victim);
+			}
+
+			for requeued in std::iter::once(net).chain(victims.iter()) {
+				if !self.route_net(*requeued, algorithm) && !still_failed.contains(requeued) {
+					still_failed.push(*requeued);
+				}
+			}
+		}
+
+		still_failed.sort();
+		return still_failed;
+	}
+
+	/// the routed nets that lie on the cheapest way to connect net,
+	/// if wires laid by the router were allowed to be crossed at a high cost
+	fn blocking_nets(&self, net : NetId) -> Vec<NetId> {
+		let crossing_cost = (self.data.x_cells() * self.data.y_cells()) as f64;
+
+		let step_cost = |from : Discrete3D, to : Discrete3D| {
+			if self.can_step(from, to, net) {
+				return Some(1.0);
+			}
+			// free cells are only in the way because of the room kept around something
+			if self.ripable(to) || self.data[to] == GridState::Free {
+				return Some(crossing_cost);
+			}
+			return None;
+		};
+
+		let paths = match self.connect_terminals(net, |tree, targets| self.best_first(tree, targets, step_cost, |_| 0.0)) {
+			Some(p) => p,
+			None => return Vec::new(), // no amount of ripping up helps
+		};
+
+		let mut blockers : Vec<NetId> = Vec::new();
+
+		for cell in paths.iter().flatten() {
+			// pads and existing tracks keep room around them too, but only routed nets can move
+			let near = self.keepout[*cell].iter()
+				.copied()
+				.filter(|x| self.routes.contains_key(x));
+
+			for other in self.owner(*cell).into_iter().chain(near) {
+				if other != net && !blockers.contains(&other) {
+					blockers.push(other);
+				}
+			}
+		}
+
+		return blockers;
+	}
+
+	/// negotiated congestion routing (pathfinder). every net is routed while cells may be shared,
+	/// then the cost of shared cells is raised and all nets are routed again, until no cell is
+	/// used by more than one net. returns the nets that couldn't be placed without sharing
+	fn negotiate(&mut self, nets : &[NetId], negotiation : &NegotiationSettings, report : &mut RouteReport) -> Vec<NetId> {
+		let mut occupancy : Grid3<usize> = self.data.same_size(0);
+		let mut history : Grid3<f64> = self.data.same_size(0.0);
+		let mut present = negotiation.present_start;
+		let mut paths : BTreeMap<NetId, Vec<Vec<Discrete3D>>> = BTreeMap::new();
+		let mut failed : Vec<NetId> = Vec::new();
+
+		for iteration in 0..negotiation.iterations {
+			for net in nets.iter() {
+				if failed.contains(net) {
+					continue;
+				}
+
+				// a net never competes with itself
+				if let Some(old) = paths.remove(net) {
+					for index in self.footprint(&old) {
+						occupancy[index] -= 1;
+					}
+				}
+
+				let step_cost = |from : Discrete3D, to : Discrete3D| {
+					if !self.can_step(from, to, *net) {
+						return None;
+					}
+
+					let index = self.index(to);
+					let mut base = negotiation.costs.move_cost(from, to) + history[index];
+					if self.near_obstacle(to, *net) {
+						base += negotiation.costs.obstacle_proximity;
+					}
+					return Some(base * (1.0 + present * occupancy[index] as f64));
+				};
+
+				let found = self.connect_terminals(*net, |tree, targets| {
+					let heuristic = |p : Discrete3D| targets.iter()
+						.map(|t| negotiation.costs.estimate(p, *t))
+						.fold(f64::INFINITY, f64::min);
+					self.best_first(tree, targets, step_cost, heuristic)
+				});
+
+				match found {
+					Some(new) => {
+						for index in self.footprint(&new) {
+							occupancy[index] += 1;
+						}
+						paths.insert(*net, new);
+					},
+					// even sharing every cell doesn't connect it
+					None => failed.push(*net),
+				}
+			}
+
+			let mut stats = CongestionStats{iteration : iteration, ..CongestionStats::default()};
+
+			for (index, users) in occupancy.iter().enumerate() {
+				if *users > 1 {
+					stats.overused_cells += 1;
+					stats.overuse += users - 1;
+					history[index] += negotiation.history_gain * (users - 1) as f64;
+				}
+			}
+
+			report.congestion.push(stats);
+
+			if stats.overused_cells == 0 {
+				break;
+			}
+
+			present *= negotiation.present_growth;
+		}
+
+		// commit in order, if sharing remains the later nets lose
+		for (net, net_paths) in paths {
+			let free = net_paths.iter()
+				.all(|path| self.path_free(net, path));
+
+			if !free {
+				failed.push(net);
+				continue;
+			}
+
+			for path in net_paths.iter() {
+				self.commit_path(net, path);
+			}
+			self.routes.entry(net).or_default().extend(net_paths);
+		}
+
+		failed.sort();
+		return failed;
+	}
+
+	/// the raster indices of all cells in paths and of the cells within half the clearance
+	/// room around them, each only once. two nets are far enough apart when theirs don't overlap
+	fn footprint(&self, paths : &[Vec<Discrete3D>]) -> Vec<usize> {
+		let mut indices : Vec<usize> = paths.iter()
+			.flat_map(|path| path.iter().copied().chain(self.around_path(path, 0.5)))
+			.map(|p| self.index(p))
+			.collect();
+		indices.sort();
+		indices.dedup();
+		return indices;
+	}
+
+	/// whether path can still be written into the raster for net
+	fn path_free(&self, net : NetId, path : &[Discrete3D]) -> bool {
+		return path.first().is_none_or(|x| self.passable(*x, net))
+			&& path.windows(2).all(|x| self.can_step(x[0], x[1], net));
+	}
+
+	/// whether the cell holds a wire or via that the router laid itself
+	fn ripable(&self, pos : Discrete3D) -> bool {
+		return matches!(self.get(pos), Some(GridState::Wire | GridState::Via));
+	}
+
+	/// removes everything the router laid down for net
+	pub fn rip_up(&mut self, net : NetId) {
+		let paths = match self.routes.remove(&net) {
+			Some(p) => p,
+			None => return,
+		};
+
+		for cell in paths.iter().flatten() {
+			if self.owner(*cell) == Some(net) && self.ripable(*cell) {
+				self.data[*cell] = GridState::Free;
+				self.owners[*cell] = None;
+			}
+		}
+
+		for path in paths.iter() {
+			let halo = self.around_path(path, 1.0);
+			self.release(net, &halo);
+		}
+	}
+
+	/// routes net with the given algorithm and writes the result into the raster.
+	/// nothing is written if any terminal can't be reached
+	fn route_net(&mut self, net : NetId, algorithm : &Algorithm) -> bool {
+		let paths = match self.connect_terminals(net, |tree, targets| match algorithm {
+			Algorithm::Lee => self.expand(net, tree, targets),
+			Algorithm::AStar(costs) => self.a_star(net, tree, targets, costs),
+			Algorithm::Negotiated(negotiation) => self.a_star(net, tree, targets, &negotiation.costs),
+		}) {
+			Some(p) => p,
+			None => return false,
+		};
+
+		for path in paths.iter() {
+			self.commit_path(net, path);
+		}
+		self.routes.entry(net).or_default().extend(paths);
+
+		return true;
+	}
+
+	/// connects all terminals of net one at a time, always growing the already connected tree.
+	/// search gets the tree and the cells of all unconnected terminals and returns a path
+	/// from the tree to one of them. None if any terminal can't be reached
+	fn connect_terminals(
+		&self,
+		net : NetId,
+		search : impl Fn(&[Discrete3D], &[Discrete3D]) -> Option<Vec<Discrete3D>>,
+	) -> Option<Vec<Vec<Discrete3D>>> {
+		let mut remaining = match self.terminals.get(&net) {
+			Some(t) if t.len() > 1 => t.clone(),
+			_ => return Some(Vec::new()),
+		};
+
+		let mut tree : Vec<Discrete3D> = remaining.remove(0);
+		let mut paths : Vec<Vec<Discrete3D>> = Vec::new();
+
+		while !remaining.is_empty() {
+			let targets : Vec<Discrete3D> = remaining.iter().flatten().copied().collect();
+
+			let path = search(&tree, &targets)?;
+
+			let reached = *path.last().unwrap();
+			let hit = remaining.iter()
+				.position(|t| t.contains(&reached))
+				.unwrap();
+
+			tree.extend(remaining.remove(hit));
+			tree.extend(path.iter().copied());
+			paths.push(path);
+		}
+
+		return Some(paths);
+	}
+
+	/// lee wave expansion from all source cells to the nearest target cell.
+	/// returns the path from a source to the reached target
+	fn expand(&self, net : NetId, sources : &[Discrete3D], targets : &[Discrete3D]) -> Option<Vec<Discrete3D>> {
+		let targets = self.target_mask(targets);
+		let unvisited = usize::MAX;
+		let mut previous = self.data.same_size(unvisited);
+		let mut queue : VecDeque<usize> = VecDeque::new();
+
+		for source in sources.iter() {
+			let index = self.index(*source);
+			if previous[index] == unvisited {
+				previous[index] = index;
+				queue.push_back(index);
+			}
+		}
+
+		while let Some(current) = queue.pop_front() {
+			if targets[current] {
+				return Some(self.backtrace(&previous, current));
+			}
+
+			for next in self.neighbours(self.point(current)) {
+				let next_index = self.index(next);
+				if previous[next_index] != unvisited || !self.can_step(self.point(current), next, net) {
+					continue;
+				}
+				previous[next_index] = current;
+				queue.push_back(next_index);
+			}
+		}
+
+		return None;
+	}
+
+	/// a* search from all source cells to the cheapest reachable target cell
+	fn a_star(&self, net : NetId, sources : &[Discrete3D], targets : &[Discrete3D], costs : &CostModel) -> Option<Vec<Discrete3D>> {
+		let step_cost = |from : Discrete3D, to : Discrete3D| {
+			if !self.can_step(from, to, net) {
+				return None;
+			}
+
+			let mut step = costs.move_cost(from, to);
+			if self.near_obstacle(to, net) {
+				step += costs.obstacle_proximity;
+			}
+			return Some(step);
+		};
+
+		let heuristic = |p : Discrete3D| targets.iter()
+			.map(|t| costs.estimate(p, *t))
+			.fold(f64::INFINITY, f64::min);
+
+		return self.best_first(sources, targets, step_cost, heuristic);
+	}
+
+	/// generic best first search. step_cost returns None for moves that aren't allowed,
+	/// heuristic has to be a lower bound of the remaining cost for the result to be optimal
+	fn best_first(
+		&self,
+		sources : &[Discrete3D],
+		targets : &[Discrete3D],
+		step_cost : impl Fn(Discrete3D, Discrete3D) -> Option<f64>,
+		heuristic : impl Fn(Discrete3D) -> f64,
+	) -> Option<Vec<Discrete3D>> {
+		let target_mask = self.target_mask(targets);
+
+		let unvisited = usize::MAX;
+		let mut previous = self.data.same_size(unvisited);
+		let mut cost = self.data.same_size(f64::INFINITY);
+		let mut closed = self.data.same_size(false);
+		let mut open : BinaryHeap<Candidate> = BinaryHeap::new();
+
+		for source in sources.iter() {
+			let index = self.index(*source);
+			previous[index] = index;
+			cost[index] = 0.0;
+			open.push(Candidate{estimate : heuristic(*source), index : index});
+		}
+
+		while let Some(Candidate{index : current, ..}) = open.pop() {
+			if closed[current] {
+				continue;
+			}
+			closed[current] = true;
+
+			if target_mask[current] {
+				return Some(self.backtrace(&previous, current));
+			}
+
+			let current_point = self.point(current);
+
+			for next in self.neighbours(current_point) {
+				let next_index = self.index(next);
+				if closed[next_index] {
+					continue;
+				}
+
+				let step = match step_cost(current_point, next) {
+					Some(c) => c,
+					None => continue,
+				};
+
+				let next_cost = cost[current] + step;
+				if next_cost < cost[next_index] {
+					cost[next_index] = next_cost;
+					previous[next_index] = current;
+					open.push(Candidate{estimate : next_cost + heuristic(next), index : next_index});
+				}
+			}
+		}
+
+		return None;
+	}
+
+	fn target_mask(&self, targets : &[Discrete3D]) -> Grid3<bool> {
+		let mut mask = self.data.same_size(false);
+		for cell in targets.iter() {
+			mask[*cell] = true;
+		}
+		return mask;
+	}
+
+	fn backtrace(&self, previous : &Grid3<usize>, end : usize) -> Vec<Discrete3D> {
+		let mut path = vec![self.point(end)];
+		let mut current = end;
+
+		while previous[current] != current {
+			current = previous[current];
+			path.push(self.point(current));
+		}
+
+		path.reverse();
+		return path;
+	}
+
+	/// turns the routed paths back into board wires and vias.
+	/// straight runs become one wire each, layer changes become vias
+	pub fn to_tracks(&self, board_params : &KicadPcb, settings : &RouterSettings) -> Result<(Vec<Wire>, Vec<Via>), KicadPcbError> {
+		let layer_name = |layer : usize| match board_params.copper_layer_name(layer) {
+			Some(name) => Ok(name),
+			None => Err(KicadPcbError::NoLayer(format!("raster layer {}", layer))),
+		};
+
+		let mut wires : Vec<Wire> = Vec::new();
+		let mut vias : Vec<Via> = Vec::new();
+
+		for (net, paths) in self.routes.iter() {
+			for path in paths.iter() {
+				let runs : Vec<&[Discrete3D]> = path
+					.chunk_by(|a, b| a.layer == b.layer)
+					.collect();
+
+				for run in runs.iter().flat_map(|x| self.new_copper(x)) {
+					for (start, end) in straight_segments(run) {
+						wires.push(Wire{
+							net_id : *net,
+							layer_name : layer_name(start.layer)?,
+							start : self.cell_centre(start),
+							end : self.cell_centre(end),
+							width : settings.track_width,
+							tstamp : new_tstamp(),
+						});
+					}
+				}
+
+				// a run of a single cell between two layer changes is a stacked via,
+				// which is put down as one via through all the layers
+				let mut i = 0;
+				while i + 1 < runs.len() {
+					let from = runs[i].last().unwrap().layer;
+					let mut to = runs[i + 1][0].layer;
+					let at = runs[i + 1][0];
+
+					while i + 2 < runs.len() && runs[i + 1].len() == 1 {
+						i += 1;
+						to = runs[i + 1][0].layer;
+					}
+
+					// through hole pads and existing vias already join the layers
+					let joined = |layer : usize| matches!(
+						self.get(Discrete3D::from(at.x, at.y, layer)),
+						Some(GridState::Pad | GridState::UserVia)
+					);
+					if joined(from) && joined(to) {
+						i += 1;
+						continue;
+					}
+
+					vias.push(Via{
+						net_id : *net,
+						at : self.cell_centre(at),
+						layers : vec![layer_name(from.min(to))?, layer_name(from.max(to))?],
+						size : settings.via_size,
+						drill : settings.via_drill,
+						tstamp : new_tstamp(),
+					});
+
+					i += 1;
+				}
+			}
+		}
+
+		return Ok((wires, vias));
+	}
+
+	/// the parts of a run on one layer that aren't copper on the board yet. steps between two
+	/// cells of existing wires or vias are left out, every part keeps the existing cell at its
+	/// ends so it still joins up with them
+	fn new_copper<'p>(&self, run : &'p [Discrete3D]) -> Vec<&'p [Discrete3D]> {
+		let existing = |p : Discrete3D| matches!(self.get(p), Some(GridState::UserWire | GridState::UserVia));
+
+		let mut parts = Vec::new();
+		let mut start : Option<usize> = None;
+
+		for i in 0..run.len().saturating_sub(1) {
+			let new_step = !(existing(run[i]) && existing(run[i + 1]));
+			match (new_step, start) {
+				(true, None) => start = Some(i),
+				(false, Some(first)) => {
+					parts.push(&run[first..=i]);
+					start = None;
+				},
+				_ => {},
+			}
+		}
+
+		if let Some(first) = start {
+			parts.push(&run[first..]);
+		}
+
+		return parts;
+	}
+
+	/// writes a path into the raster and keeps other nets a clearance away from it.
+	/// layer changes become vias, pads and existing vias along the path stay what they are
+	fn commit_path(&mut self, net : NetId, path : &[Discrete3D]) {
+		for (i, cell) in path.iter().enumerate() {
+			let state = match (self.data[*cell], changes_layer(path, i)) {
+				(GridState::Pad, _) => GridState::Pad,
+				(GridState::UserVia, _) => GridState::UserVia,
+				(GridState::UserWire, _) => GridState::UserWire,
+				(_, true) => GridState::Via,
+				(GridState::Via, false) => GridState::Via,
+				(_, false) => GridState::Wire,
+			};
+
+			self.occupy(*cell, state, net);
+		}
+
+		let halo = self.around_path(path, 1.0);
+		self.keep_out(net, &halo);
+	}
+}
+
+
+/// whether the path goes to another layer right before or after its cell i
+fn changes_layer(path : &[Discrete3D], i : usize) -> bool {
+	return path.get(i + 1).is_some_and(|n| n.layer != path[i].layer)
+		|| (i > 0 && path[i - 1].layer != path[i].layer);
+}
+
+/// distance of the board position p from the line from a to b
+pub(crate) fn segment_distance(p : V2, a : V2, b : V2) -> f64 {
+	let d = [b[0] - a[0], b[1] - a[1]];
+	let length_squared = d[0] * d[0] + d[1] * d[1];
+	let t = match length_squared > 0.0 {
+		true => (((p[0] - a[0]) * d[0] + (p[1] - a[1]) * d[1]) / length_squared).clamp(0.0, 1.0),
+		false => 0.0,
+	};
+	return (p[0] - a[0] - t * d[0]).hypot(p[1] - a[1] - t * d[1]);
+}
+
+
+/// splits a path on one layer into its straight pieces, as (start, end) pairs
+fn straight_segments(run : &[Discrete3D]) -> Vec<(Discrete3D, Discrete3D)> {
+	let direction = |a : Discrete3D, b : Discrete3D| (b.x as isize - a.x as isize, b.y as isize - a.y as isize);
+	let mut segments = Vec::new();
+
+	if run.len() < 2 {
+		return segments;
 	}
+
+	let mut start = run[0];
+
+	for i in 1..run.len() - 1 {
+		if direction(run[i - 1], run[i]) != direction(run[i], run[i + 1]) {
+			segments.push((start, run[i]));
+			start = run[i];
+		}
+	}
+
+	segments.push((start, run[run.len() - 1]));
+	return segments;
+}
+
+#[test]
+fn test_straight_segments() {
+	let run = vec![
+		Discrete3D::from(0, 0, 0),
+		Discrete3D::from(1, 0, 0),
+		Discrete3D::from(2, 0, 0),
+		Discrete3D::from(2, 1, 0),
+		Discrete3D::from(2, 2, 0),
+	];
+
+	assert_eq!(straight_segments(&run), vec![
+		(Discrete3D::from(0, 0, 0), Discrete3D::from(2, 0, 0)),
+		(Discrete3D::from(2, 0, 0), Discrete3D::from(2, 2, 0)),
+	]);
+	assert!(straight_segments(&run[..1]).is_empty());
 }
 
 
 #[derive(
-	Debug, 
-	Default, 
-	Copy, 
-	Clone, 
+	Debug,
+	Default,
+	Copy,
+	Clone,
 	PartialEq
 )]
-enum GridState {
+pub enum GridState {
 	#[default]
 	Free,
 	Pad,
@@ -118,26 +1167,480 @@ enum GridState {
 	Via,
 	UserWire, //so they cant get removed
 	UserVia,
+	Blocked, // outside the board outline or inside a cutout
 }
 
 
 #[test]
 fn test_raster_gen() {
 	let test_pcb = KicadPcb::from_file("./test_pcb/test_pcb.kicad_pcb").unwrap();
-	let mut raster = Raster::new(&test_pcb);
+	let raster = Raster::new(&test_pcb, &RouterSettings::default());
+
+	assert_eq!(raster.data.layers(), 2);
+	assert_eq!(raster.data.len(), raster.data.x_cells() * raster.data.y_cells() * raster.data.layers());
+}
+
+#[test]
+fn test_raster_pad_layers() {
+	let mut test_pcb = KicadPcb::from_file("./test_pcb/test_pcb.kicad_pcb").unwrap();
+	test_pcb.wires.clear();
+	test_pcb.vias.clear();
+
+	// spread the footprints out so their pads sit apart from each other
+	for (i, footprint) in test_pcb.footprints.iter_mut().enumerate() {
+		for pad in footprint.pads.iter_mut() {
+			pad.abs_at = [2.0 + 2.0 * i as f64 + pad.at[0] / 4.0, 5.0];
+		}
+	}
+
+	let raster = Raster::new(&test_pcb, &RouterSettings::default());
+	let layers_of = |at : V2| {
+		return (0..raster.data.layers())
+			.filter(|l| raster.get(raster.get_discrete(at, *l).unwrap()) == Some(GridState::Pad))
+			.collect::<Vec<usize>>();
+	};
+
+	// the connector pins are through hole, the led and resistor pads sit on the front
+	assert_eq!(layers_of(test_pcb.footprints[0].pads[0].abs_at), vec!{0, 1});
+	assert_eq!(layers_of(test_pcb.footprints[1].pads[0].abs_at), vec!{0});
+	assert_eq!(layers_of(test_pcb.footprints[2].pads[0].abs_at), vec!{0, 1});
+	assert_eq!(layers_of(test_pcb.footprints[3].pads[1].abs_at), vec!{0});
+}
+
+#[test]
+fn test_raster_pad_cells() {
+	let raster = Raster::blank(40, 40, 2, 0.1);
+
+	let mut pad = Pad{shape : crate::router::PadShape::Rect, abs_at : [2.0, 2.0], size : [1.0, 0.4], ..Pad::default()};
+	assert_eq!(raster.pad_cells(&pad, 1, 0.0).len(), 10 * 4);
+	assert!(raster.pad_cells(&pad, 1, 0.0).iter().all(|x| x.layer == 1));
+
+	pad.angle = 90.0;
+	let cells = raster.pad_cells(&pad, 0, 0.0);
+	assert_eq!(cells.len(), 4 * 10);
+	assert!(cells.iter().all(|x| (18..22).contains(&x.x) && (15..25).contains(&x.y)));
+
+	// smaller than a cell, still there
+	pad.size = [0.01, 0.01];
+	assert_eq!(raster.pad_cells(&pad, 0, 0.0), vec!{Discrete3D::from(20, 20, 0)});
+
+	// hanging over the edge of the raster
+	pad.abs_at = [0.0, 0.0];
+	pad.size = [0.4, 0.4];
+	assert_eq!(raster.pad_cells(&pad, 0, 0.0).len(), 2 * 2);
+}
+
+#[test]
+fn test_raster_outline() {
+	let mut test_pcb = KicadPcb::from_file("./test_pcb/test_pcb.kicad_pcb").unwrap();
+	test_pcb.footprints.clear();
+	test_pcb.wires.clear();
+	test_pcb.vias.clear();
+
+	// a 6 by 4 mm board with a square hole
+	test_pcb.outline = Outline{polygons : vec!{
+		vec!{[0.0, 0.0], [6.0, 0.0], [6.0, 4.0], [0.0, 4.0]},
+		vec!{[2.0, 1.0], [3.0, 1.0], [3.0, 2.0], [2.0, 2.0]},
+	}};
+
+	let raster = Raster::new(&test_pcb, &RouterSettings::default());
+	assert_eq!((raster.data.x_cells(), raster.data.y_cells()), (60, 40));
+
+	for layer in 0..raster.data.layers() {
+		assert_eq!(raster.get(Discrete3D::from(10, 10, layer)), Some(GridState::Free));
+		assert_eq!(raster.get(Discrete3D::from(25, 15, layer)), Some(GridState::Blocked));
+		assert!(!raster.passable(Discrete3D::from(25, 15, layer), 1));
+	}
 }
 
 #[test]
 fn test_raster_get_set() {
-	unimplemented!();
+	let mut raster = Raster::blank(4, 3, 2, 0.1);
+
+	let point = Discrete3D::from(3, 2, 1);
+	assert_eq!(raster.get(point), Some(GridState::Free));
+	raster.set(point, GridState::Wire);
+	assert_eq!(raster.get(point), Some(GridState::Wire));
+	assert_eq!(raster.get(Discrete3D::from(3, 2, 0)), Some(GridState::Free));
+
+	assert_eq!(raster.get(Discrete3D::from(4, 0, 0)), None);
+	assert_eq!(raster.get(Discrete3D::from(0, 0, 2)), None);
+	assert_eq!(raster.owner(Discrete3D::from(0, 3, 0)), None);
 }
 
 #[test]
 fn test_raster_get_discrete() {
-	unimplemented!();
+	let raster = Raster::blank(10, 10, 2, 0.5);
+
+	assert_eq!(raster.get_discrete([0.0, 0.0], 0), Some(Discrete3D::from(0, 0, 0)));
+	assert_eq!(raster.get_discrete([1.2, 2.6], 1), Some(Discrete3D::from(2, 5, 1)));
+
+	// negative positions used to wrap around to huge cells
+	assert_eq!(raster.get_discrete([-0.1, 1.0], 0), None);
+	assert_eq!(raster.get_discrete([5.0, 1.0], 0), None);
+	assert_eq!(raster.get_discrete([1.0, 1.0], 2), None);
+}
+
+#[test]
+fn test_raster_origin() {
+	let mut raster = Raster::blank(100, 50, 2, 0.1);
+	raster.origin = [-2.0, 30.0];
+
+	assert_eq!(raster.get_discrete([-2.0, 30.0], 0), Some(Discrete3D::from(0, 0, 0)));
+	assert_eq!(raster.get_discrete([-1.95, 30.3], 0), Some(Discrete3D::from(0, 3, 0))); // 0.3 / 0.1 is just below 3
+	assert_eq!(raster.get_discrete([7.99, 34.99], 1), Some(Discrete3D::from(99, 49, 1)));
+	assert_eq!(raster.get_discrete([8.0, 31.0], 0), None);
+	assert_eq!(raster.get_discrete([1.0, 29.9], 0), None);
+
+	let point = Discrete3D::from(37, 12, 1);
+	assert_eq!(raster.get_continuous(point), [1.7, 31.2]);
+	assert_eq!(raster.get_discrete(raster.get_continuous(point), 1), Some(point));
+}
+
+#[test]
+fn test_raster_extent() {
+	let mut test_pcb = KicadPcb::from_file("./test_pcb/test_pcb.kicad_pcb").unwrap();
+
+	// without an outline the raster covers the pads, with some room around them
+	let [low, high] = test_pcb.bounding_box().unwrap();
+	let raster = Raster::new(&test_pcb, &RouterSettings::default());
+	assert!(raster.origin[0] <= low[0] && raster.origin[1] <= low[1]);
+	assert!(raster.get_continuous(Discrete3D::from(raster.data.x_cells(), raster.data.y_cells(), 0))[0] >= high[0]);
+	for pad in test_pcb.footprints.iter().flat_map(|x| x.pads.iter()) {
+		assert!(raster.get_discrete(pad.abs_at, 0).is_some());
+	}
+
+	// an outline decides on its own
+	test_pcb.outline = Outline{polygons : vec!{vec!{[20.0, 30.0], [50.0, 30.0], [50.0, 50.0], [20.0, 50.0]}}};
+	let raster = Raster::new(&test_pcb, &RouterSettings::default());
+	assert_eq!(raster.origin, [20.0, 30.0]);
+	assert_eq!((raster.data.x_cells(), raster.data.y_cells()), (300, 200));
 }
 
 #[test]
 fn test_raster_get_route() {
-	unimplemented!();
-}
\ No newline at end of file
+	let mut raster = Raster::blank(10, 10, 2, 0.1);
+
+	// a wall on the top layer forces the route through the bottom layer
+	for y in 0..10 {
+		raster.set(Discrete3D::from(5, y, 0), GridState::UserWire);
+	}
+
+	let start = Discrete3D::from(1, 1, 0);
+	let end = Discrete3D::from(8, 8, 0);
+	raster.occupy(start, GridState::Pad, 1);
+	raster.occupy(end, GridState::Pad, 1);
+	raster.add_terminal(1, vec![start]);
+	raster.add_terminal(1, vec![end]);
+
+	let report = raster.route(&RouterSettings::default());
+
+	assert_eq!(report.routed, vec![1]);
+	assert!(report.failed.is_empty());
+
+	let path = &raster.routes(1)[0];
+	assert_eq!(path.first(), Some(&start));
+	assert_eq!(path.last(), Some(&end));
+	assert_eq!(path.len(), 7 + 7 + 2 + 1);
+	assert!(path.iter().any(|p| raster.get(*p) == Some(GridState::Via)));
+	assert!(path.iter().all(|p| raster.owner(*p) == Some(1)));
+}
+
+#[test]
+fn test_raster_route_blocked() {
+	let mut raster = Raster::blank(10, 10, 2, 0.1);
+
+	for layer in 0..2 {
+		for y in 0..10 {
+			raster.occupy(Discrete3D::from(5, y, layer), GridState::UserWire, 2);
+		}
+	}
+
+	raster.occupy(Discrete3D::from(1, 1, 0), GridState::Pad, 1);
+	raster.occupy(Discrete3D::from(8, 8, 0), GridState::Pad, 1);
+	raster.add_terminal(1, vec![Discrete3D::from(1, 1, 0)]);
+	raster.add_terminal(1, vec![Discrete3D::from(8, 8, 0)]);
+
+	let report = raster.route(&RouterSettings::default());
+
+	assert_eq!(report.failed, vec![1]);
+	assert!(raster.routes(1).is_empty());
+	assert_eq!(raster.get(Discrete3D::from(2, 1, 0)), Some(GridState::Free));
+}
+
+#[test]
+fn test_raster_route_a_star() {
+	let mut raster = Raster::blank(10, 10, 2, 0.1);
+
+	let start = Discrete3D::from(1, 1, 0);
+	let end = Discrete3D::from(8, 1, 0);
+	raster.occupy(start, GridState::Pad, 1);
+	raster.occupy(end, GridState::Pad, 1);
+	raster.add_terminal(1, vec![start]);
+	raster.add_terminal(1, vec![end]);
+
+	let settings = RouterSettings{
+		algorithm : Algorithm::AStar(CostModel::default()),
+		..RouterSettings::default()
+	};
+	let report = raster.route(&settings);
+
+	assert_eq!(report.routed, vec![1]);
+
+	// horizontal on the top layer is the preferred direction, so the route stays straight
+	let path = &raster.routes(1)[0];
+	assert_eq!(path.len(), 8);
+	assert!(path.iter().all(|p| p.y == 1 && p.layer == 0));
+}
+
+#[test]
+fn test_cost_model_direction() {
+	let costs = CostModel::default();
+
+	let horizontal_top = costs.move_cost(Discrete3D::from(0, 0, 0), Discrete3D::from(1, 0, 0));
+	let vertical_top = costs.move_cost(Discrete3D::from(0, 0, 0), Discrete3D::from(0, 1, 0));
+	let vertical_bottom = costs.move_cost(Discrete3D::from(0, 0, 1), Discrete3D::from(0, 1, 1));
+	let via = costs.move_cost(Discrete3D::from(0, 0, 0), Discrete3D::from(0, 0, 1));
+
+	assert_eq!(horizontal_top, costs.step);
+	assert_eq!(vertical_bottom, costs.step);
+	assert_eq!(vertical_top, costs.step + costs.wrong_direction);
+	assert_eq!(via, costs.via);
+}
+
+/// a one layer board where net 1 takes the short way through the middle of the second row,
+/// which is the only way for net 2 to get from one pad to the other
+#[cfg(test)]
+fn crossing_board() -> Raster {
+	let mut raster = Raster::blank(5, 4, 1, 0.1);
+
+	for (net, x, y) in [(1, 0, 1), (1, 4, 1), (2, 2, 0), (2, 2, 2)] {
+		let cell = Discrete3D::from(x, y, 0);
+		raster.occupy(cell, GridState::Pad, net);
+		raster.add_terminal(net, vec![cell]);
+	}
+	return raster;
+}
+
+#[test]
+fn test_raster_rip_up_and_reroute() {
+	let mut single_pass = crossing_board();
+	let report = single_pass.route(&RouterSettings::default());
+	assert_eq!(report.failed, vec![2]);
+	assert_eq!(report.passes, 0);
+
+	let mut with_passes = crossing_board();
+	let settings = RouterSettings{
+		max_passes : 3,
+		..RouterSettings::default()
+	};
+	let report = with_passes.route(&settings);
+
+	assert_eq!(report.routed, vec![1, 2]);
+	assert!(report.failed.is_empty());
+	assert_eq!(report.passes, 1);
+	assert_eq!(with_passes.owner(Discrete3D::from(2, 1, 0)), Some(2));
+	assert_eq!(with_passes.owner(Discrete3D::from(2, 3, 0)), Some(1));
+}
+
+#[test]
+fn test_raster_rip_up() {
+	let mut raster = Raster::blank(5, 1, 1, 0.1);
+
+	for x in [0, 4] {
+		let cell = Discrete3D::from(x, 0, 0);
+		raster.occupy(cell, GridState::Pad, 1);
+		raster.add_terminal(1, vec![cell]);
+	}
+
+	raster.route(&RouterSettings::default());
+	assert_eq!(raster.get(Discrete3D::from(2, 0, 0)), Some(GridState::Wire));
+
+	raster.rip_up(1);
+	assert_eq!(raster.get(Discrete3D::from(2, 0, 0)), Some(GridState::Free));
+	assert_eq!(raster.get(Discrete3D::from(0, 0, 0)), Some(GridState::Pad));
+	assert!(raster.routes(1).is_empty());
+
+	// a layer change on an existing wire of the net leaves the wire alone
+	let mut raster = Raster::blank(3, 1, 2, 0.1);
+	let wire = Discrete3D::from(1, 0, 0);
+	raster.occupy(wire, GridState::UserWire, 1);
+	raster.commit_path(1, &[Discrete3D::from(0, 0, 0), wire, Discrete3D::from(1, 0, 1)]);
+	raster.routes.insert(1, vec!{vec!{wire}});
+
+	raster.rip_up(1);
+	assert_eq!(raster.get(wire), Some(GridState::UserWire));
+	assert_eq!(raster.owner(wire), Some(1));
+}
+
+#[test]
+fn test_raster_clearance() {
+	// tracks of other nets have to stay a track width and the clearance, 0.45 mm, from the
+	// middle of a routed wire
+	let mut raster = Raster::blank(30, 20, 2, 0.1);
+	raster.track_width = 0.25;
+	raster.via_size = 0.8;
+	raster.clearance = 0.2;
+
+	let path : Vec<Discrete3D> = (0..30).map(|x| Discrete3D::from(x, 10, 0)).collect();
+	raster.commit_path(2, &path);
+	raster.routes.insert(2, vec!{path});
+
+	assert!(!raster.passable(Discrete3D::from(15, 6, 0), 1));
+	assert!(raster.passable(Discrete3D::from(15, 5, 0), 1));
+	assert!(raster.passable(Discrete3D::from(15, 6, 0), 2));
+	assert!(raster.passable(Discrete3D::from(15, 10, 1), 1));
+
+	// a via is wider than a track
+	assert!(!raster.can_step(Discrete3D::from(15, 5, 0), Discrete3D::from(15, 5, 1), 1));
+	assert!(raster.can_step(Discrete3D::from(15, 2, 0), Discrete3D::from(15, 2, 1), 1));
+
+	raster.rip_up(2);
+	assert!(raster.passable(Discrete3D::from(15, 6, 0), 1));
+	assert!(raster.keepout.iter().all(|x| x.is_empty()));
+
+	// net 1 has to go down a corridor that only the room around the wire of net 2 blocks,
+	// which goes away when net 2 is ripped up
+	let mut raster = Raster::blank(30, 20, 1, 0.1);
+	raster.track_width = 0.25;
+	raster.clearance = 0.2;
+
+	for y in 0..20 {
+		raster.set(Discrete3D::from(14, y, 0), GridState::Blocked);
+		raster.set(Discrete3D::from(18, y, 0), GridState::Blocked);
+	}
+	for y in [0, 19] {
+		let cell = Discrete3D::from(16, y, 0);
+		raster.occupy(cell, GridState::Pad, 1);
+		raster.add_terminal(1, vec![cell]);
+	}
+
+	let path : Vec<Discrete3D> = (19..30).map(|x| Discrete3D::from(x, 10, 0)).collect();
+	raster.commit_path(2, &path);
+	raster.routes.insert(2, vec!{path});
+
+	assert!(!raster.route_net(1, &Algorithm::Lee));
+	assert_eq!(raster.blocking_nets(1), vec![2]);
+}
+
+#[test]
+fn test_raster_pad_clearance() {
+	let mut test_pcb = KicadPcb::from_file("./test_pcb/test_pcb.kicad_pcb").unwrap();
+	let settings = RouterSettings::default();
+	let pad = test_pcb.footprints[0].pads[0].clone();
+	assert_eq!((pad.abs_at, pad.size), ([28.0, 39.0], [2.0, 2.0]));
+
+	// the edge of the pad is 1 mm from its middle, tracks of other nets stay another
+	// clearance and half a track, 0.325 mm, away from it
+	let cell = |raster : &Raster, x : f64| raster.get_discrete([x, 39.0], 0).unwrap();
+	let other = pad.net.id + 100;
+
+	let raster = Raster::new(&test_pcb, &settings);
+	assert!(!raster.passable(cell(&raster, 29.25), other));
+	assert!(raster.passable(cell(&raster, 29.45), other));
+	assert!(raster.passable(cell(&raster, 29.25), pad.net.id));
+
+	// unless the pad has its own clearance
+	test_pcb.footprints[0].pads[0].clearance = Some(0.5);
+	let raster = Raster::new(&test_pcb, &settings);
+	assert!(!raster.passable(cell(&raster, 29.45), other));
+}
+
+#[test]
+fn test_raster_negotiated() {
+	// net 1 has to learn to leave the middle to net 2
+	let mut raster = crossing_board();
+
+	let settings = RouterSettings{
+		algorithm : Algorithm::Negotiated(NegotiationSettings::default()),
+		..RouterSettings::default()
+	};
+	let report = raster.route(&settings);
+
+	assert_eq!(report.routed, vec![1, 2]);
+	assert!(report.failed.is_empty());
+	assert!(report.congestion.len() > 1);
+	assert!(report.congestion[0].overused_cells > 0);
+	assert_eq!(report.congestion.last().unwrap().overused_cells, 0);
+	assert_eq!(raster.owner(Discrete3D::from(2, 1, 0)), Some(2));
+}
+
+#[test]
+fn test_raster_to_tracks() {
+	let test_pcb = KicadPcb::from_file("./test_pcb/test_pcb.kicad_pcb").unwrap();
+	let mut raster = Raster::blank(10, 10, 2, 0.1);
+
+	// a wall on the top layer forces the route through the bottom layer
+	for y in 0..10 {
+		raster.set(Discrete3D::from(5, y, 0), GridState::UserWire);
+	}
+
+	for x in [1, 8] {
+		let cell = Discrete3D::from(x, 1, 0);
+		raster.occupy(cell, GridState::Pad, 1);
+		raster.add_terminal(1, vec![cell]);
+	}
+
+	let settings = RouterSettings::default();
+	raster.route(&settings);
+	let (wires, vias) = raster.to_tracks(&test_pcb, &settings).unwrap();
+
+	assert_eq!(vias.len(), 2);
+	assert!(vias.iter().all(|v| v.layers == vec!["F.Cu".to_string(), "B.Cu".to_string()]));
+	assert!(wires.iter().all(|w| w.net_id == 1 && w.width == settings.track_width));
+
+	// the wires have to add up to the way from one pad to the other
+	let length : f64 = wires.iter()
+		.map(|w| (w.end[0] - w.start[0]).hypot(w.end[1] - w.start[1]))
+		.sum();
+	assert!((length - 0.7).abs() < 1e-9);
+}
+
+#[test]
+fn test_raster_to_tracks_existing() {
+	let test_pcb = KicadPcb::from_file("./test_pcb/test_pcb.kicad_pcb").unwrap();
+	let mut raster = Raster::blank(10, 1, 1, 0.1);
+
+	// part of the way is already an existing wire of the net
+	for x in 3..=6 {
+		raster.occupy(Discrete3D::from(x, 0, 0), GridState::UserWire, 1);
+	}
+	for x in [0, 9] {
+		let cell = Discrete3D::from(x, 0, 0);
+		raster.occupy(cell, GridState::Pad, 1);
+		raster.add_terminal(1, vec![cell]);
+	}
+
+	let settings = RouterSettings::default();
+	raster.route(&settings);
+	let (wires, vias) = raster.to_tracks(&test_pcb, &settings).unwrap();
+
+	assert!(vias.is_empty());
+	let ends : Vec<(V2, V2)> = wires.iter().map(|w| (w.start, w.end)).collect();
+	assert_eq!(ends, vec!{([0.05, 0.05], [0.35, 0.05]), ([0.65, 0.05], [0.95, 0.05])});
+}
+
+#[test]
+fn test_raster_join_connected_terminals() {
+	let mut raster = Raster::blank(10, 3, 2, 0.1);
+
+	// pads at both ends of row 0 and one on the bottom layer of row 2
+	for cell in [Discrete3D::from(0, 0, 0), Discrete3D::from(9, 0, 0), Discrete3D::from(9, 2, 1)] {
+		raster.occupy(cell, GridState::Pad, 1);
+		raster.add_terminal(1, vec![cell]);
+	}
+
+	// a diagonal wire joins the first two, a wire on the other layer only passes under the third
+	for x in 1..9 {
+		raster.occupy(Discrete3D::from(x, x % 2, 0), GridState::UserWire, 1);
+	}
+	raster.occupy(Discrete3D::from(9, 1, 0), GridState::UserWire, 1);
+	raster.occupy(Discrete3D::from(9, 2, 0), GridState::UserWire, 1);
+
+	raster.join_connected_terminals();
+
+	let terminals = &raster.terminals[&1];
+	assert_eq!(terminals.len(), 2);
+	assert_eq!(terminals[0].len(), 1 + 8 + 1 + 2);
+	assert_eq!(terminals[1], vec!{Discrete3D::from(9, 2, 1)});
+}
@@ -1,8 +1,24 @@
+use std::collections::BTreeMap;
+use std::collections::HashSet;
 use std::fs::read_to_string;
-
-use crate::s_exp_parser::SExpr;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::s_exp_parser::LosslessNode;
+use crate::s_exp_parser::ParseError;
+use crate::s_exp_parser::{SExpr, SExprRef};
 use crate::s_exp_parser;
 use crate::value::*;
+use crate::convert::*;
+use crate::outline;
+use crate::outline::Outline;
+use crate::raster_solver::CongestionStats;
+use crate::raster_solver::CostModel;
+use crate::raster_solver::NegotiationSettings;
+use crate::raster_solver::Raster;
+use crate::raster_solver::segment_distance;
 
 
 pub type NetId = usize;
@@ -14,18 +30,40 @@ pub type V2 = [f64; 2];
 pub enum KicadPcbError {
 	IoError(std::io::Error),
 	FileType,
-	ParseFail,
-	PcbNetFail,
-	GeneralFail,
-	FootprintFail,
-	PadFail,
-	WireFail,
-	ViaFail,
+	ParseFail(ParseError),
+	FieldFail{element : String, id : String, error : FieldError}, // id is the tstamp or uuid, if there is one
 	NoLayer(String),
 	Other(String),
 }
 
 
+impl KicadPcbError {
+	/// the error for a field of exp, which is an element like segment, that couldn't be read
+	pub(crate) fn field_fail(element : &str, exp : &SExprRef, error : FieldError) -> Self {
+		return KicadPcbError::FieldFail{
+			element : element.to_string(),
+			id : tstamp_of(exp),
+			error : error,
+		};
+	}
+}
+
+
+impl std::fmt::Display for KicadPcbError {
+	fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			KicadPcbError::IoError(e) => write!(f, "{}", e),
+			KicadPcbError::FileType => write!(f, "not a .kicad_pcb file"),
+			KicadPcbError::ParseFail(e) => write!(f, "{}", e),
+			KicadPcbError::FieldFail{element, id, error} if id.is_empty() => write!(f, "could not read {}: {}", element, error),
+			KicadPcbError::FieldFail{element, id, error} => write!(f, "could not read {} {}: {}", element, id, error),
+			KicadPcbError::NoLayer(layer) => write!(f, "unknown layer {}", layer),
+			KicadPcbError::Other(message) => write!(f, "{}", message),
+		}
+	}
+}
+
+
 #[derive(Debug, Copy, Clone, Default, PartialEq)]
 pub enum LayerType {
 	#[default]
@@ -33,6 +71,27 @@ pub enum LayerType {
 	Signal,
 }
 
+// every kind of layer that isn't for tracks counts as user
+impl FromValue for LayerType {
+	const EXPECTED : &'static str = "a layer type";
+
+	fn from_value(value : &Value) -> Option<Self> {
+		return match value_to_string(value)?.as_str() {
+			"signal" => Some(LayerType::Signal),
+			_ => Some(LayerType::User),
+		};
+	}
+}
+
+impl ToValue for LayerType {
+	fn to_value(&self) -> Value<'static> {
+		return Value::Symbol(match self {
+			LayerType::Signal => "signal".into(),
+			LayerType::User => "user".into(),
+		});
+	}
+}
+
 
 #[derive(Debug, Default, Clone)]
 pub struct Wire {
@@ -40,97 +99,158 @@ pub struct Wire {
 	pub layer_name : String,
 	pub start : V2,
 	pub end : V2,
+	pub width : f64,
+	pub tstamp : String,
 }
 
 
+s_expr_fields!{Wire {
+	Child("start") => start : V2,
+	Child("end") => end : V2,
+	ChildOrDefault("width") => width : f64,
+	Child("layer") => layer_name : String,
+	Child("net") => net_id : NetId,
+	Tstamp => tstamp : String,
+}}
+
 impl Wire {
-	pub fn from_exp(exp : &SExpr) -> Result<Self, KicadPcbError> {
-		let get_err = KicadPcbError::WireFail;
-
-		let start = match exp.get("start")
-			.get(0) {
-				Some(s) => s,
-				None => return Err(get_err),				
-			}.values()
-			.iter()
-			.map(|x| match value_to_float(&x) {
-				Some(v) => v,
-				None => panic!("{:?}", x),
-			}) // maybe make this a match
-			.collect::<Vec<f64>>()
-			.try_into()
-			.unwrap(); //maybe make this a match
-
-		let end = match exp.get("end")
-			.get(0) {
-				Some(s) => s,
-				None => return Err(get_err),				
-			}.values()
-			.iter()
-			.map(|x| match value_to_float(&x) {
-				Some(v) => v,
-				None => panic!("{:?}", x),
-			}) // maybe make this a match
-			.collect::<Vec<f64>>()
-			.try_into()
-			.unwrap(); //maybe make this a match
-
-		let net_id = value_to_int(&exp.get("net")[0].values()[0]).unwrap() as usize;
-
-		let layer_name = value_to_string(&exp.get("layer")[0].values()[0]).unwrap();
-
-		return Ok(Wire{
-			net_id : net_id,
-			layer_name : layer_name,
-			start : start,
-			end : end,
-		});
+	pub fn from_exp(exp : &SExprRef) -> Result<Self, KicadPcbError> {
+		return Wire::from_s_expr(exp).map_err(|e| KicadPcbError::field_fail("segment", exp, e));
+	}
+
+	pub fn as_s_expr(&self) -> SExpr<'static> {
+		let mut exp = SExpr::named("segment");
+		self.write_into(&mut exp);
+		return exp;
 	}
 }
 
+#[test]
+fn test_wire_s_expr() {
+	let test_string = "(segment (start 37.7625 38.6375) (end 37.4 39) (width 0.25) (layer \"F.Cu\") (net 2) (tstamp 3ba3cee6-48b9-48e2-a466-821ec0c8a1c3))";
+	let exp = s_exp_parser::parse(test_string).unwrap();
+	let wire = Wire::from_exp(&exp.get("segment")[0]).unwrap();
+
+	assert_eq!(wire.width, 0.25);
+	assert_eq!(wire.tstamp, "3ba3cee6-48b9-48e2-a466-821ec0c8a1c3");
+
+	let mut root = SExpr::new();
+	root.append_exp(wire.as_s_expr());
+	let reread = Wire::from_exp(&s_exp_parser::parse(&root.print()).unwrap().get("segment")[0]).unwrap();
+
+	assert_eq!(reread.start, wire.start);
+	assert_eq!(reread.end, wire.end);
+	assert_eq!(reread.width, wire.width);
+	assert_eq!(reread.layer_name, wire.layer_name);
+	assert_eq!(reread.net_id, wire.net_id);
+	assert_eq!(reread.tstamp, wire.tstamp);
+}
+
 
 #[derive(Debug, Default, Clone)]
 pub struct Via {
 	pub net_id : NetId,
 	pub at : V2,
 	pub layers : Vec<String>,
+	pub size : f64,
+	pub drill : f64,
+	pub tstamp : String,
 }
 
+s_expr_fields!{Via {
+	Child("at") => at : V2,
+	ChildOrDefault("size") => size : f64,
+	ChildOrDefault("drill") => drill : f64,
+	Child("layers") => layers : Vec<String>,
+	Child("net") => net_id : NetId,
+	Tstamp => tstamp : String,
+}}
+
 impl Via {
-	pub fn from_exp(exp : &SExpr) -> Result<Self, KicadPcbError> {
-		let get_err = KicadPcbError::ViaFail;
-
-		let at = match exp.get("at")
-			.get(0) {
-				Some(s) => s,
-				None => return Err(get_err),				
-			}.values()
-			.iter()
-			.map(|x| match value_to_float(&x) {
-				Some(v) => v,
-				None => panic!("{:?}", x),
-			}) // maybe make this a match
-			.collect::<Vec<f64>>()
-			.try_into()
-			.unwrap(); //maybe make this a match
-
-
-		let layers = exp.get("layers")[0]
-			.values()
-			.iter()
-			.filter_map(value_to_string)
-			.collect();
+	pub fn from_exp(exp : &SExprRef) -> Result<Self, KicadPcbError> {
+		return Via::from_s_expr(exp).map_err(|e| KicadPcbError::field_fail("via", exp, e));
+	}
 
-		let net_id = value_to_int(
-			&exp.get("net")[0].values()[0]
-		).unwrap() as usize;
+	pub fn as_s_expr(&self) -> SExpr<'static> {
+		let mut exp = SExpr::named("via");
+		self.write_into(&mut exp);
+		return exp;
+	}
+}
 
-		return Ok(Via{
-			at : at,
-			layers : layers,
-			net_id : net_id,
-		});
 
+// the tstamp of an element, or its uuid in newer files. empty for the ones that have neither
+fn tstamp_of(exp : &SExprRef) -> String {
+	return exp.value("tstamp/0")
+		.or_else(|| exp.value("uuid/0"))
+		.map(value_as_string)
+		.unwrap_or_default();
+}
+
+// tstamps are written bare, unlike other strings
+fn write_tstamp(exp : &mut SExpr, tstamp : &str) {
+	if !tstamp.is_empty() {
+		exp.append_exp(SExpr::with_values("tstamp", &[Value::Symbol(tstamp.to_string().into())]));
+	}
+}
+
+// the tstamp or uuid of an element, written back as a tstamp
+struct Tstamp;
+
+impl Slot<String> for Tstamp {
+	fn read(&self, exp : &SExprRef) -> Result<String, FieldError> {
+		return Ok(tstamp_of(exp));
+	}
+
+	fn write(&self, exp : &mut SExpr, tstamp : &String) {
+		write_tstamp(exp, tstamp);
+	}
+}
+
+#[test]
+fn test_via_s_expr() {
+	let test_string = "(via (at 25 33) (size 0.8) (drill 0.4) (layers \"F.Cu\" \"B.Cu\") (free) (net 0) (tstamp dccd888c-bd1f-409b-a72b-57ff96a44736))";
+	let exp = s_exp_parser::parse(test_string).unwrap();
+	let via = Via::from_exp(&exp.get("via")[0]).unwrap();
+
+	assert_eq!(via.size, 0.8);
+	assert_eq!(via.drill, 0.4);
+
+	let mut root = SExpr::new();
+	root.append_exp(via.as_s_expr());
+	let reread = Via::from_exp(&s_exp_parser::parse(&root.print()).unwrap().get("via")[0]).unwrap();
+
+	assert_eq!(reread.at, via.at);
+	assert_eq!(reread.layers, via.layers);
+	assert_eq!(reread.size, via.size);
+	assert_eq!(reread.drill, via.drill);
+	assert_eq!(reread.net_id, via.net_id);
+	assert_eq!(reread.tstamp, via.tstamp);
+}
+
+#[test]
+fn test_from_exp_errors() {
+	let exp = s_exp_parser::parse("(segment (end 1 2) (width 0.25) (layer \"F.Cu\") (net 1))
+		(via (at 1 x) (layers \"F.Cu\" \"B.Cu\") (net 1) (tstamp dccd888c-bd1f-409b-a72b-57ff96a44736))
+		(footprint \"R\" (layer \"F.Cu\") (at 1 2 90) (uuid 05a6a192-0ed0-41d3-b217-a49c8e2fb570)
+			(pad \"1\" smd rect (at 0 0 90) (size 1 1) (layers \"F.Cu\")))").unwrap();
+
+	let wire_err = Wire::from_exp(&exp.get("segment")[0]).unwrap_err();
+	assert_eq!(wire_err.to_string(), "could not read segment: start is missing");
+
+	let via_err = Via::from_exp(&exp.get("via")[0]).unwrap_err();
+	assert_eq!(via_err.to_string(), "could not read via dccd888c-bd1f-409b-a72b-57ff96a44736: at/1 should be a number, found x");
+
+	// angles after the position used to break the extraction
+	assert!(Footprint::from_exp(&exp.get("footprint")[0]).is_ok());
+
+	let board = exp.view();
+	match get_layers(&board).unwrap_err() {
+		KicadPcbError::FieldFail{element, error, ..} => {
+			assert_eq!(element, "kicad_pcb");
+			assert_eq!(error, FieldError::missing("layers"));
+		},
+		other => panic!("{:?}", other),
 	}
 }
 
@@ -147,48 +267,33 @@ impl PartialEq for PcbNet {
 	fn eq(&self, other : &Self) -> bool {
 		self.id == other.id && self.name == other.name
 	}
-
-	fn ne(&self, other : &Self) -> bool {
-		! (self == other)
-	}
-
 }
 
-impl PcbNet {
-	pub fn from_exp(exp : &SExpr) -> Result<Self, KicadPcbError> {
-		let values = exp.values();
-
-		let id = match match values.get(0) {
-			Some(s) => value_to_int(s),
-			None => return Err(KicadPcbError::Other(format!("{:?}", exp))),
-		} {
-			Some(v) => v,
-			None => return Err(KicadPcbError::PcbNetFail),
-		};
+s_expr_fields!{PcbNet {
+	Nth(0) => id : NetId,
+	Nth(1) => name : String,
+}}
 
-		let name = match match values.get(1) {
-			Some(s) => value_to_string(s),
-			None => return Err(KicadPcbError::PcbNetFail),
-		} {
-			Some(v) => v,
-			None => return Err(KicadPcbError::PcbNetFail),
-		};
+impl PcbNet {
+	pub fn from_exp(exp : &SExprRef) -> Result<Self, KicadPcbError> {
+		return PcbNet::from_s_expr(exp).map_err(|e| KicadPcbError::field_fail("net", exp, e));
+	}
 
-		return Ok(PcbNet {
-			id : id as usize,
-			name : name,
-		});
+	pub fn as_s_expr(&self) -> SExpr<'static> {
+		let mut exp = SExpr::named("net");
+		self.write_into(&mut exp);
+		return exp;
 	}
 }
 
 #[test]
 fn test_pcb_net_from_exp() {
 	let test_string = "(net 1 \"GND\")";
-	let exp = s_exp_parser::parse(&test_string).unwrap();
+	let exp = s_exp_parser::parse(test_string).unwrap();
 	let net = PcbNet::from_exp(&exp.get("net")[0]).unwrap();
 
 	assert_eq!(net.id, 1);
-	assert_eq!(net.name, "\"GND\"");
+	assert_eq!(net.name, "GND");
 }
 
 
@@ -207,181 +312,771 @@ pub struct PcbLayer {
 }
 
 
+s_expr_fields!{PcbLayer {
+	Nth(0) => id : LayerId,
+	Nth(1) => name : String,
+	Nth(2) => layer_type : LayerType,
+	NthOrDefault(3) => attrib : String,
+}}
+
 impl PcbLayer {
-	pub fn from_exp(exp : &SExpr) -> Result<Self, KicadPcbError> {
-		let get_err = KicadPcbError::NoLayer(exp.print());
+	pub fn from_exp(exp : &SExprRef) -> Result<Self, KicadPcbError> {
+		return PcbLayer::from_s_expr(exp).map_err(|e| KicadPcbError::field_fail("layer", exp, e));
+	}
+
+	pub fn as_s_expr(&self) -> SExpr<'static> {
+		let mut exp = SExpr::new();
+		self.write_into(&mut exp);
+		return exp;
+	}
+}
+
+
+/// the layers named by a (layers ...) list, with wildcards like *.Cu, F&B.Cu and *.Mask
+/// expanded against the layers of the board
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LayerSet {
+	pub names : Vec<String>,
+}
 
-		if exp.values().len() == 0 {
-			return Err(get_err);
+impl LayerSet {
+	/// names that aren't patterns are kept even if the board doesn't have such a layer
+	pub fn expand(patterns : &[String], layers : &[PcbLayer]) -> Self {
+		let mut names : Vec<String> = Vec::new();
+
+		for pattern in patterns.iter() {
+			let is_pattern = pattern.starts_with("*.") || pattern.contains('&');
+			let matching : Vec<String> = match is_pattern {
+				true => layers.iter()
+					.filter(|x| layer_matches(pattern, &x.name))
+					.map(|x| x.name.clone())
+					.collect(),
+				false => vec!{pattern.clone()},
+			};
+
+			for name in matching {
+				if !names.contains(&name) {
+					names.push(name);
+				}
+			}
 		}
 
-		let id = match value_to_int(&exp.values()[0]) {
-			Some(v) => v, 
-			None => return Err(get_err),
+		return LayerSet{names : names};
+	}
+
+	pub fn contains(&self, name : &str) -> bool {
+		return self.names.iter().any(|x| x == name);
+	}
+
+	pub fn iter(&self) -> std::slice::Iter<'_, String> {
+		return self.names.iter();
+	}
+}
+
+// if the layer called name is one of the layers pattern stands for. *.Cu is any copper layer,
+// F&B.Cu is F.Cu and B.Cu
+fn layer_matches(pattern : &str, name : &str) -> bool {
+	let (sides, kind) = match pattern.split_once('.') {
+		Some(split) => split,
+		None => return pattern == name,
+	};
+
+	let (side, layer_kind) = match name.split_once('.') {
+		Some(split) => split,
+		None => return false,
+	};
+
+	if kind != layer_kind {
+		return false;
+	}
+
+	return sides == "*" || sides.split('&').any(|x| x == side);
+}
+
+#[test]
+fn test_layer_set() {
+	let test_pcb = KicadPcb::from_file("./test_pcb/test_pcb.kicad_pcb").unwrap();
+	let expand = |patterns : &[&str]| {
+		let patterns : Vec<String> = patterns.iter().map(|x| x.to_string()).collect();
+		return LayerSet::expand(&patterns, &test_pcb.layers).names;
+	};
+
+	assert_eq!(expand(&["*.Cu"]), vec!{"F.Cu", "B.Cu"});
+	assert_eq!(expand(&["F&B.Cu"]), vec!{"F.Cu", "B.Cu"});
+	assert_eq!(expand(&["*.Cu", "*.Mask"]), vec!{"F.Cu", "B.Cu", "B.Mask", "F.Mask"});
+	assert_eq!(expand(&["F.Cu", "F.Paste", "F.Mask"]), vec!{"F.Cu", "F.Paste", "F.Mask"});
+	assert_eq!(expand(&["F.Cu", "*.Cu"]), vec!{"F.Cu", "B.Cu"});
+	assert_eq!(expand(&["*.Fab"]), vec!{"B.Fab", "F.Fab"});
+	assert!(expand(&["*.Nothing"]).is_empty());
+
+	let set = LayerSet::expand(&["*.Cu".to_string()], &test_pcb.layers);
+	assert!(set.contains("B.Cu"));
+	assert!(!set.contains("*.Cu"));
+	assert!(!set.contains("F.Mask"));
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct PcbGeneral {
+	pub thickness: f64,
+}
+
+s_expr_fields!{PcbGeneral {
+	Child("thickness") => thickness : f64,
+}}
+
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum PadType {
+	#[default]
+	Smd,
+	ThruHole,
+	NpThruHole, // a plain hole, not connected to anything
+	Connect, // copper without paste or a hole, like edge connector fingers
+}
+
+impl FromValue for PadType {
+	const EXPECTED : &'static str = "a pad type";
+
+	fn from_value(value : &Value) -> Option<Self> {
+		return match value_to_string(value)?.as_str() {
+			"smd" => Some(PadType::Smd),
+			"thru_hole" => Some(PadType::ThruHole),
+			"np_thru_hole" => Some(PadType::NpThruHole),
+			"connect" => Some(PadType::Connect),
+			_ => None,
 		};
+	}
+}
+
+impl ToValue for PadType {
+	fn to_value(&self) -> Value<'static> {
+		return Value::Symbol(match self {
+			PadType::Smd => "smd".into(),
+			PadType::ThruHole => "thru_hole".into(),
+			PadType::NpThruHole => "np_thru_hole".into(),
+			PadType::Connect => "connect".into(),
+		});
+	}
+}
+
 
-		let name = match value_to_string(&exp.values()[1]) {
-			Some(v) => v, 
-			None => return Err(get_err),
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum PadShape {
+	Circle,
+	#[default]
+	Rect,
+	Oval,
+	RoundRect,
+	Trapezoid,
+	Custom, // an anchor pad with primitives drawn around it
+}
+
+impl FromValue for PadShape {
+	const EXPECTED : &'static str = "a pad shape";
+
+	fn from_value(value : &Value) -> Option<Self> {
+		return match value_to_string(value)?.as_str() {
+			"circle" => Some(PadShape::Circle),
+			"rect" => Some(PadShape::Rect),
+			"oval" => Some(PadShape::Oval),
+			"roundrect" => Some(PadShape::RoundRect),
+			"trapezoid" => Some(PadShape::Trapezoid),
+			"custom" => Some(PadShape::Custom),
+			_ => None,
 		};
+	}
+}
+
+impl ToValue for PadShape {
+	fn to_value(&self) -> Value<'static> {
+		return Value::Symbol(match self {
+			PadShape::Circle => "circle".into(),
+			PadShape::Rect => "rect".into(),
+			PadShape::Oval => "oval".into(),
+			PadShape::RoundRect => "roundrect".into(),
+			PadShape::Trapezoid => "trapezoid".into(),
+			PadShape::Custom => "custom".into(),
+		});
+	}
+}
+
+
+/// the hole of a pad, either (drill 1) or (drill oval 1.2 0.8), with an optional (offset x y)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Drill {
+	pub oval : bool,
+	pub size : V2, // both the same for round holes
+	pub offset : V2,
+}
 
-		let signal = Some("signal".to_string());
+impl FromSExpr for Drill {
+	fn from_s_expr(exp : &SExprRef) -> Result<Self, FieldError> {
+		let oval = exp.values().first() == Some(&&Value::Symbol("oval".into()));
+		let first = if oval {1} else {0};
 
-/*
-		let layer_type = match value_to_string(&exp.values()[2]) {
-			signal => LayerType::Signal,
-			Some(_) => LayerType::User,
-			None => return Err(get_err),
+		let width : f64 = value_at(exp, first)?;
+		let height = match exp.values().get(first + 1) {
+			Some(_) => value_at(exp, first + 1)?,
+			None => width,
 		};
 
-*/
-		let mut layer_type = LayerType::User;
+		return Ok(Drill{
+			oval : oval,
+			size : [width, height],
+			offset : field::<Option<V2>>(exp, "offset")?.unwrap_or_default(),
+		});
+	}
+}
 
-		if value_to_string(&exp.values()[2]) == signal {
-			layer_type = LayerType::Signal;
+// the values depend on each other, so this is written out instead of a table
+impl ToSExpr for Drill {
+	fn write_into(&self, exp : &mut SExpr) {
+		if self.oval {
+			exp.append_value(Value::Symbol("oval".into()));
 		}
 
-		let attrib = match exp.values().get(3) {
-			Some(value) => value_to_string(&value).unwrap(),
-			None => String::new(),
-		};
+		exp.append_value(self.size[0].to_value());
+		if self.oval || self.size[1] != self.size[0] {
+			exp.append_value(self.size[1].to_value());
+		}
 
+		if self.offset != [0.0, 0.0] {
+			write_field(exp, "offset", &self.offset);
+		}
+	}
+}
 
-		return Ok(PcbLayer {
-			id : id as usize,
-			name : name,
-			layer_type : layer_type,
-			attrib : attrib,
-		});
+
+/// one piece of copper of a custom pad, in the frame of the pad
+#[derive(Debug, Clone, PartialEq)]
+pub enum PadPrimitive {
+	Line{points : Vec<V2>, width : f64}, // gr_line and gr_arc, as a chain of straight pieces
+	Polygon{points : Vec<V2>, width : f64, filled : bool}, // gr_poly and gr_rect
+	Circle{center : V2, radius : f64, width : f64, filled : bool},
+}
+
+// exp is the (primitives ...) of a pad. curves aren't read, pcbnew hardly writes them
+impl FromSExpr for Vec<PadPrimitive> {
+	fn from_s_expr(exp : &SExprRef) -> Result<Self, FieldError> {
+		let mut primitives = Vec::new();
+
+		for kind in ["gr_line", "gr_arc", "gr_poly", "gr_rect", "gr_circle"] {
+			for shape in exp.select(kind) {
+				primitives.push(PadPrimitive::read(kind, &shape).map_err(|e| e.inside(kind))?);
+			}
+		}
+
+		return Ok(primitives);
 	}
 }
 
+impl ToSExpr for Vec<PadPrimitive> {
+	fn write_into(&self, exp : &mut SExpr) {
+		for primitive in self.iter() {
+			primitive.write_into(exp);
+		}
+	}
+}
 
-#[derive(Debug, Clone, Default)]
-pub struct PcbGeneral {
-	pub thickness: f64,
+impl PadPrimitive {
+	fn read(kind : &str, exp : &SExprRef) -> Result<Self, FieldError> {
+		let width = field::<Option<f64>>(exp, "width")?.unwrap_or_default();
+
+		// polygons are filled unless told otherwise. a rectangle or circle without a width
+		// would be invisible, so those are filled too
+		let filled = match field::<Option<String>>(exp, "fill")?.as_deref() {
+			Some("yes") | Some("solid") => true,
+			Some(_) => false,
+			None => kind == "gr_poly" || width == 0.0,
+		};
+
+		return Ok(match kind {
+			"gr_line" => PadPrimitive::Line{points : outline::read_line(exp)?, width : width},
+			"gr_arc" => PadPrimitive::Line{points : outline::read_arc(exp)?, width : width},
+			"gr_poly" => PadPrimitive::Polygon{points : outline::read_poly(exp)?, width : width, filled : filled},
+			"gr_rect" => PadPrimitive::Polygon{points : outline::read_rect(exp)?, width : width, filled : filled},
+			_ => {
+				let center : V2 = field(exp, "center")?;
+				let end : V2 = field(exp, "end")?;
+				let radius = (end[0] - center[0]).hypot(end[1] - center[1]);
+				PadPrimitive::Circle{center : center, radius : radius, width : width, filled : filled}
+			},
+		});
+	}
+
+	// appends the primitive to the (primitives ...) of a pad. a line is written as the straight
+	// pieces it was read as, a rectangle as a polygon
+	fn write_into(&self, exp : &mut SExpr) {
+		let write_fill = |shape : &mut SExpr, filled : bool| {
+			shape.append_exp(SExpr::with_values("fill", &[Value::Symbol(if filled {"yes"} else {"none"}.into())]));
+		};
+
+		match self {
+			PadPrimitive::Line{points, width} => {
+				for piece in points.windows(2) {
+					let mut shape = SExpr::named("gr_line");
+					write_field(&mut shape, "start", &piece[0]);
+					write_field(&mut shape, "end", &piece[1]);
+					write_field(&mut shape, "width", width);
+					exp.append_exp(shape);
+				}
+			},
+			PadPrimitive::Polygon{points, width, filled} => {
+				let mut pts = SExpr::named("pts");
+				for point in points.iter() {
+					write_field(&mut pts, "xy", point);
+				}
+
+				let mut shape = SExpr::named("gr_poly");
+				shape.append_exp(pts);
+				write_field(&mut shape, "width", width);
+				write_fill(&mut shape, *filled);
+				exp.append_exp(shape);
+			},
+			PadPrimitive::Circle{center, radius, width, filled} => {
+				let mut shape = SExpr::named("gr_circle");
+				write_field(&mut shape, "center", center);
+				write_field(&mut shape, "end", &[center[0] + radius, center[1]]);
+				write_field(&mut shape, "width", width);
+				write_fill(&mut shape, *filled);
+				exp.append_exp(shape);
+			},
+		}
+	}
+
+	/// if the primitive, grown by margin, covers at, which is in the frame of the pad
+	fn covers(&self, at : V2, margin : f64) -> bool {
+		return match self {
+			PadPrimitive::Line{points, width} => near_outline(points, false, at, width / 2.0 + margin),
+			PadPrimitive::Polygon{points, width, filled} => {
+				(*filled && outline::polygon_contains(points, at)) || near_outline(points, true, at, width / 2.0 + margin)
+			},
+			PadPrimitive::Circle{center, radius, width, filled} => {
+				let distance = (at[0] - center[0]).hypot(at[1] - center[1]);
+				let reach = width / 2.0 + margin;
+				(*filled && distance <= radius + reach) || (distance - radius).abs() <= reach
+			},
+		};
+	}
+
+	/// how far the primitive reaches from the centre of the pad
+	fn reach(&self) -> f64 {
+		return match self {
+			PadPrimitive::Line{points, width} | PadPrimitive::Polygon{points, width, ..} => {
+				points.iter().map(|p| p[0].hypot(p[1])).fold(0.0, f64::max) + width / 2.0
+			},
+			PadPrimitive::Circle{center, radius, width, ..} => center[0].hypot(center[1]) + radius + width / 2.0,
+		};
+	}
 }
 
+// if at is no further than reach from the line through points. closed joins the last point
+// back to the first
+fn near_outline(points : &[V2], closed : bool, at : V2, reach : f64) -> bool {
+	let pieces = if closed {points.len()} else {points.len().saturating_sub(1)};
+	return (0..pieces).any(|i| segment_distance(at, points[i], points[(i + 1) % points.len()]) <= reach);
+}
 
-#[derive(Debug, Default, Clone)]
+
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct Pad {
+	pub number : String, // empty for mounting holes
+	pub pad_type : PadType,
+	pub shape : PadShape,
 	pub layer : Vec<String>,
-	pub at : V2,
+	pub at : V2, // relative to the footprint, before it is rotated
 	pub abs_at : V2,
+	pub angle : f64, // orientation on the board in degrees, the footprint rotation is already in it
+	pub size : V2,
+	pub drill : Option<Drill>,
+	pub roundrect_rratio : f64, // corner radius as a fraction of the shorter side
+	pub rect_delta : V2, // how much longer one end of a trapezoid is than the other, along y or x
+	pub primitives : Vec<PadPrimitive>, // the copper of a custom pad besides its anchor
+	pub clearance : Option<f64>, // overrides the clearance of the net class if set
 	pub net : PcbNet,
-	//may need more fields
+}
+
+s_expr_fields!{Pad {
+	PadNumber => number : String,
+	Nth(1) => pad_type : PadType,
+	Nth(2) => shape : PadShape,
+	Child("at") => at : V2,
+	Angle => angle : f64,
+	Child("size") => size : V2,
+	ChildOrDefault("rect_delta") => rect_delta : V2,
+	Child("drill") => drill : Option<Drill>,
+	Child("layers") => layer : Vec<String>,
+	ChildOrDefault("roundrect_rratio") => roundrect_rratio : f64,
+	ChildOrDefault("primitives") => primitives : Vec<PadPrimitive>,
+	Child("clearance") => clearance : Option<f64>,
+	ChildOrDefault("net") => net : PcbNet, // unconnected pads have none
+	Derived => abs_at : V2,
+} then |pad : &mut Pad| pad.abs_at = pad.at}
+
+// pad numbers are usually quoted, but older files have them bare. mounting holes have none
+struct PadNumber;
+
+impl Slot<String> for PadNumber {
+	fn read(&self, exp : &SExprRef) -> Result<String, FieldError> {
+		return Ok(exp.values()
+			.first()
+			.map(|x| value_to_string(x).unwrap_or_else(|| value_as_string(x)))
+			.unwrap_or_default());
+	}
+
+	fn write(&self, exp : &mut SExpr, number : &String) {
+		exp.append_value(number.to_value());
+	}
 }
 
 impl Pad {
-	pub fn from_exp(exp : &SExpr) -> Result<Self, KicadPcbError> {
-		let get_err = KicadPcbError::PadFail;
-		let mut pad = Pad::default();
-
-		pad.layer = match exp.get("layers")
-			.get(0) {
-				Some(s) => s,
-				None => return Err(get_err),
-			}
-			.values()
-			.iter()
-			.map(|x| value_to_string(&x).unwrap()) //maybe replace unwrap with a match
-			.collect();
+	pub fn from_exp(exp : &SExprRef) -> Result<Self, KicadPcbError> {
+		return Pad::from_s_expr(exp).map_err(|e| KicadPcbError::field_fail("pad", exp, e));
+	}
+
+	/// if the copper of the pad, grown by margin on every side, covers the board position at.
+	/// the anchor of a custom pad is taken as a rectangle, even if it is round
+	pub fn covers(&self, at : V2, margin : f64) -> bool {
+		// into the frame of the pad, where its sides are parallel to the axes
+		let local = rotate([at[0] - self.abs_at[0], at[1] - self.abs_at[1]], -self.angle);
+		let half = [self.size[0] / 2.0 + margin, self.size[1] / 2.0 + margin];
+		let x = local[0].abs();
+		let y = local[1].abs();
+
+		// how far the point is outside a rectangle shrunk by radius, which then gets round corners
+		let outside_rounded = |radius : f64| {
+			let dx = (x - (half[0] - radius)).max(0.0);
+			let dy = (y - (half[1] - radius)).max(0.0);
+			return dx.hypot(dy) <= radius;
+		};
+
+		return match self.shape {
+			PadShape::Circle => x.hypot(y) <= half[0],
+			PadShape::Oval => outside_rounded(half[0].min(half[1])),
+			PadShape::RoundRect => outside_rounded(self.roundrect_rratio * self.size[0].min(self.size[1]) + margin),
+			PadShape::Rect => x <= half[0] && y <= half[1],
+			PadShape::Trapezoid => {
+				let corners = self.trapezoid_corners();
+				outline::polygon_contains(&corners, local) || near_outline(&corners, true, local, margin)
+			},
+			PadShape::Custom => {
+				(x <= half[0] && y <= half[1]) || self.primitives.iter().any(|p| p.covers(local, margin))
+			},
+		};
+	}
 
-		pad.at = match exp.get("at")
-			.get(0) {
-				Some(s) => s,
-				None => return Err(get_err),				
-			}.values()
-			.iter()
-			.map(|x| match value_to_float(&x) {
-				Some(v) => v,
-				None => panic!("{:?}", x),
-			}) // maybe make this a match
-			.collect::<Vec<f64>>()
-			.try_into()
-			.unwrap(); //maybe make this a match
+	/// how far the copper of the pad reaches from its centre
+	pub fn reach(&self) -> f64 {
+		let anchor = match self.shape {
+			PadShape::Trapezoid => self.trapezoid_corners().iter().map(|p| p[0].hypot(p[1])).fold(0.0, f64::max),
+			_ => self.size[0].hypot(self.size[1]) / 2.0,
+		};
 
-		pad.abs_at = pad.at;
+		return self.primitives.iter().map(PadPrimitive::reach).fold(anchor, f64::max);
+	}
 
-		return Ok(pad);
+	// the corners of a trapezoid in the frame of the pad, the way pcbnew builds them.
+	// a rect_delta along y makes the bottom wider and the top narrower, along x the left
+	// side longer and the right one shorter
+	fn trapezoid_corners(&self) -> [V2; 4] {
+		let half = [self.size[0] / 2.0, self.size[1] / 2.0];
+		let delta = [self.rect_delta[0] / 2.0, self.rect_delta[1] / 2.0];
+
+		return [
+			[-half[0] - delta[1], half[1] + delta[0]],
+			[-half[0] + delta[1], -half[1] - delta[0]],
+			[half[0] - delta[1], -half[1] + delta[0]],
+			[half[0] + delta[1], half[1] - delta[0]],
+		];
 	}
 }
 
 #[test]
 fn test_pad_from_exp() {
 	let test_string = "(pad \"1\" smd roundrect (at -1.4 0) (size 1.25 2.65) (layers \"F.Cu\" \"F.Paste\" \"F.Mask\") (roundrect_rratio 0.2)
-      (net 1 \"GND\") (pinfunction \"K\") (pintype \"passive\") (tstamp 2b94d621-c132-4657-b654-d69cf5549fbe))";
-	
-	let exp = s_exp_parser::parse(&test_string).unwrap();
-	let pad = Pad::from_exp(&exp.get("pad")[0]).unwrap();
+      (net 1 \"GND\") (pinfunction \"K\") (pintype \"passive\") (tstamp 2b94d621-c132-4657-b654-d69cf5549fbe))
+		(pad 2 thru_hole oval (at 0 0 90) (size 2 1) (drill oval 1.2 0.6 (offset 0.1 0)) (layers *.Cu) (clearance 0.3))
+		(pad \"\" np_thru_hole circle (at 0 0) (size 3 3) (drill 3) (layers *.Cu *.Mask))";
+
+	let exp = s_exp_parser::parse(test_string).unwrap();
+	let pads = exp.get("pad");
+	let pad = Pad::from_exp(&pads[0]).unwrap();
 
 	assert_eq!(pad.at, [-1.4, 0.0]);
+	assert_eq!(pad.number, "1");
+	assert_eq!(pad.pad_type, PadType::Smd);
+	assert_eq!(pad.shape, PadShape::RoundRect);
+	assert_eq!(pad.size, [1.25, 2.65]);
+	assert_eq!(pad.drill, None);
+	assert_eq!(pad.roundrect_rratio, 0.2);
+	assert_eq!(pad.clearance, None);
+	assert_eq!(pad.net, PcbNet{id : 1, name : "GND".to_string()});
+
+	let pad = Pad::from_exp(&pads[1]).unwrap();
+	assert_eq!(pad.number, "2");
+	assert_eq!(pad.pad_type, PadType::ThruHole);
+	assert_eq!(pad.shape, PadShape::Oval);
+	assert_eq!(pad.drill, Some(Drill{oval : true, size : [1.2, 0.6], offset : [0.1, 0.0]}));
+	assert_eq!(pad.clearance, Some(0.3));
+	assert_eq!(pad.net.id, 0);
+
+	let pad = Pad::from_exp(&pads[2]).unwrap();
+	assert_eq!(pad.number, "");
+	assert_eq!(pad.pad_type, PadType::NpThruHole);
+	assert_eq!(pad.drill, Some(Drill{oval : false, size : [3.0, 3.0], offset : [0.0, 0.0]}));
+
+	let broken = s_exp_parser::parse("(pad \"1\" smd hexagon (at 0 0) (size 1 1) (layers \"F.Cu\"))").unwrap();
+	assert_eq!(
+		Pad::from_exp(&broken.get("pad")[0]).unwrap_err().to_string(),
+		"could not read pad: 2 should be a pad shape, found hexagon"
+	);
 }
 
+#[test]
+fn test_pad_s_expr() {
+	let test_string = "(pad \"1\" thru_hole oval (at 1 2 90) (size 2 1) (drill oval 1.2 0.6 (offset 0.1 0)) (layers *.Cu) (clearance 0.3) (net 1 \"GND\"))
+		(pad 2 smd trapezoid (at 0 0) (size 3 2) (rect_delta 0 1) (layers \"F.Cu\"))
+		(pad \"\" np_thru_hole circle (at 0 0) (size 3 3) (drill 3) (layers *.Cu *.Mask))
+		(pad \"4\" smd custom (at 0 0) (size 1 1) (layers \"F.Cu\")
+			(primitives
+				(gr_rect (start 0 0) (end 2 1) (width 0.1) (fill yes))
+				(gr_line (start 0 0) (end 0 2) (width 0.4))
+				(gr_circle (center -3 0) (end -2 0) (width 0.2))))";
+
+	let exp = s_exp_parser::parse(test_string).unwrap();
+
+	for pad in exp.get("pad").iter() {
+		let pad = Pad::from_exp(pad).unwrap();
+
+		let mut written = SExpr::named("pad");
+		pad.write_into(&mut written);
+		let mut root = SExpr::new();
+		root.append_exp(written);
+
+		let reread = Pad::from_exp(&s_exp_parser::parse(&root.print()).unwrap().get("pad")[0]).unwrap();
+		assert_eq!(reread, pad);
+	}
+}
 
+#[test]
+fn test_pad_covers() {
+	let mut pad = Pad{shape : PadShape::Rect, abs_at : [10.0, 10.0], size : [2.0, 1.0], ..Pad::default()};
+	assert!(pad.covers([10.9, 10.4], 0.0));
+	assert!(!pad.covers([10.9, 10.6], 0.0));
+	assert!(pad.covers([10.9, 10.6], 0.2));
+
+	// turned upright
+	pad.angle = 90.0;
+	assert!(!pad.covers([10.9, 10.4], 0.0));
+	assert!(pad.covers([10.4, 10.9], 0.0));
+
+	pad.angle = 0.0;
+	pad.shape = PadShape::Oval;
+	assert!(pad.covers([10.5, 10.45], 0.0));
+	assert!(!pad.covers([10.95, 10.45], 0.0));
+
+	pad.shape = PadShape::RoundRect;
+	pad.roundrect_rratio = 0.25;
+	assert!(pad.covers([10.95, 10.0], 0.0));
+	assert!(!pad.covers([10.99, 10.49], 0.0));
+
+	pad.shape = PadShape::Circle;
+	pad.size = [2.0, 2.0];
+	assert!(pad.covers([10.7, 10.7], 0.0));
+	assert!(!pad.covers([10.8, 10.8], 0.0));
+}
 
-#[derive(Debug, Clone, Default)]
+#[test]
+fn test_trapezoid_covers() {
+	// 2 wide at the top, 4 at the bottom
+	let test_string = "(pad \"1\" smd trapezoid (at 10 10) (size 3 2) (rect_delta 0 1) (layers \"F.Cu\"))";
+	let exp = s_exp_parser::parse(test_string).unwrap();
+	let pad = Pad::from_exp(&exp.get("pad")[0]).unwrap();
+	assert_eq!(pad.rect_delta, [0.0, 1.0]);
+
+	assert!(pad.covers([11.9, 10.9], 0.0));
+	assert!(!pad.covers([11.9, 9.1], 0.0)); // inside the rectangle of size, but not the trapezoid
+	assert!(!pad.covers([11.6, 10.0], 0.0));
+	assert!(pad.covers([11.6, 10.0], 0.2));
+	assert!((pad.reach() - 2f64.hypot(1.0)).abs() < 1e-9);
+}
+
+#[test]
+fn test_custom_pad_covers() {
+	let test_string = "(pad \"1\" smd custom (at 10 10 90) (size 1 1) (layers \"F.Cu\")
+		(options (clearance outline) (anchor rect))
+		(primitives
+			(gr_poly (pts (xy 0 -0.5) (xy 3 -0.5) (xy 3 0.5) (xy 0 0.5)) (width 0))
+			(gr_line (start 0 0) (end 0 2) (width 0.4))
+			(gr_circle (center -3 0) (end -2 0) (width 0.2))))";
+
+	let exp = s_exp_parser::parse(test_string).unwrap();
+	let pad = Pad::from_exp(&exp.get("pad")[0]).unwrap();
+	assert_eq!(pad.primitives.len(), 3);
+	assert_eq!(pad.primitives[2], PadPrimitive::Circle{center : [-3.0, 0.0], radius : 1.0, width : 0.2, filled : false});
+
+	// the polygon points up after turning the pad by 90 degrees
+	assert!(pad.covers([10.0, 10.0], 0.0));
+	assert!(pad.covers([10.0, 7.2], 0.0));
+	assert!(!pad.covers([10.0, 6.8], 0.0));
+	assert!(pad.covers([10.0, 6.8], 0.3));
+
+	// the line goes to the right, 0.2 either side of it
+	assert!(pad.covers([11.9, 10.15], 0.0));
+	assert!(!pad.covers([11.9, 10.3], 0.0));
+
+	// a ring down below, empty in the middle
+	assert!(pad.covers([10.0, 12.0], 0.0));
+	assert!(!pad.covers([10.0, 13.0], 0.0));
+	assert!(pad.covers([10.0, 14.05], 0.0));
+
+	assert_eq!(pad.reach(), 4.1);
+}
+
+
+
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Footprint {
 	pub name : String,
 	pub layer : String,
 	pub at : V2,
+	pub angle : f64, // rotation in degrees, counterclockwise as seen from the front
 	pub pads : Vec<Pad>,
 	//may need more fields
 }
 
 
+// only what is modelled is written, the rest of a footprint is left to the lossless tree
+s_expr_fields!{Footprint {
+	Nth(0) => name : String,
+	Child("layer") => layer : String,
+	Child("at") => at : V2,
+	Angle => angle : f64,
+	Children("pad") => pads : Vec<Pad>,
+} then Footprint::place_pads}
+
 impl Footprint {
-	pub fn from_exp(exp : &SExpr) -> Result<Self, KicadPcbError> {
-		let get_err = KicadPcbError::FootprintFail;
+	pub fn from_exp(exp : &SExprRef) -> Result<Self, KicadPcbError> {
+		return Footprint::from_s_expr(exp).map_err(|e| KicadPcbError::field_fail("footprint", exp, e));
+	}
 
-		let mut footprint = Footprint::default();
+	/// if the footprint was flipped to the bottom of the board
+	pub fn is_flipped(&self) -> bool {
+		return self.layer == "B.Cu";
+	}
 
-		footprint.name = exp.get_name();
+	// writes the absolute positions of the pads. pcbnew stores the pads of footprints
+	// on B.Cu already mirrored, so only the rotation is left to do for either side
+	fn place_pads(&mut self) {
+		for pad in self.pads.iter_mut() {
+			let offset = rotate(pad.at, self.angle);
+			pad.abs_at = [self.at[0] + offset[0], self.at[1] + offset[1]];
+		}
+	}
+}
 
+// the optional angle in (at x y angle), 0 if there is none
+fn angle_of(exp : &SExprRef) -> Result<f64, FieldError> {
+	return Ok(field::<Vec<f64>>(exp, "at")?.get(2).copied().unwrap_or_default());
+}
 
-		footprint.pads = exp.get("pad").iter()
-			.filter_map(|x| match Pad::from_exp(&x) {
-				Ok(v) => Some(v), 
-				_ => None
-			})
-			.collect();
+// the angle in (at x y angle), which has to come after the at in the table
+struct Angle;
 
-		footprint.layer = match value_to_string(
-			& match exp.get("layer").get(0) {
-				Some(l) => l,
-				None => return Err(get_err),
-			}.values()[0]
-		){
-			Some(l) => l,
-			None => return Err(get_err),
-		};
+impl Slot<f64> for Angle {
+	fn read(&self, exp : &SExprRef) -> Result<f64, FieldError> {
+		return angle_of(exp);
+	}
 
-		footprint.at = match exp.get("at")
-			.get(0) {
-				Some(s) => s,
-				None => return Err(get_err),				
-			}.values()
-			.iter()
-			.map(|x| match value_to_float(&x) {
-				Some(v) => v,
-				None => panic!("{:?}", x),
-			}) // maybe make this a match
-			.collect::<Vec<f64>>()
-			.try_into()
-			.unwrap(); //maybe make this a match
+	fn write(&self, exp : &mut SExpr, angle : &f64) {
+		if *angle != 0.0 {
+			if let Some(at) = exp.find_mut("at") {
+				at.append_value(angle.to_value());
+			}
+		}
+	}
+}
 
-		// write the absolute positions of the pads
-		footprint.pads = footprint.pads
-			.iter()
-			.map(|p| {
-				let mut r = p.clone();
-				r.abs_at[0] += footprint.at[0];
-				r.abs_at[1] += footprint.at[1];
-				return r;
-			}).collect();
 
-		return Ok(footprint);
-	}
+/// v rotated by angle degrees, counterclockwise on screen. board coordinates have y pointing
+/// down, so this is the same direction pcbnew rotates in
+pub fn rotate(v : V2, angle : f64) -> V2 {
+	let (sin, cos) = angle.to_radians().sin_cos();
+	let rotated = [v[0] * cos + v[1] * sin, -v[0] * sin + v[1] * cos];
+
+	// keep right angles exact, a pad at 1e-17 is just noise
+	return rotated.map(|x| (x * 1e9).round() / 1e9);
+}
+
+#[test]
+fn test_rotate() {
+	assert_eq!(rotate([1.0, 0.0], 0.0), [1.0, 0.0]);
+	assert_eq!(rotate([1.0, 0.0], 90.0), [0.0, -1.0]);
+	assert_eq!(rotate([1.0, 2.0], 180.0), [-1.0, -2.0]);
+	assert_eq!(rotate([0.0, 1.0], -90.0), [-1.0, 0.0]);
+	assert_eq!(rotate([2.0, 0.0], 45.0), [2f64.sqrt(), -(2f64.sqrt())].map(|x| (x * 1e9).round() / 1e9));
+}
+
+#[test]
+fn test_footprint_rotation() {
+	let test_string = "(footprint \"R\" (layer \"F.Cu\") (at 10 20 90)
+			(pad \"1\" smd rect (at -1 0 90) (size 1 1) (layers \"F.Cu\"))
+			(pad \"2\" smd rect (at 1 0.5 90) (size 1 1) (layers \"F.Cu\")))
+		(footprint \"R\" (layer \"B.Cu\") (at 10 20 180)
+			(pad \"1\" smd rect (at -1 0.5 180) (size 1 1) (layers \"B.Cu\")))";
+
+	let exp = s_exp_parser::parse(test_string).unwrap();
+	let footprints = exp.select("footprint");
+
+	let front = Footprint::from_exp(&footprints[0]).unwrap();
+	assert_eq!(front.angle, 90.0);
+	assert_eq!(front.pads[0].abs_at, [10.0, 21.0]);
+	assert_eq!(front.pads[1].abs_at, [10.5, 19.0]);
+	assert_eq!(front.pads[1].angle, 90.0);
+	assert!(!front.is_flipped());
+
+	let back = Footprint::from_exp(&footprints[1]).unwrap();
+	assert!(back.is_flipped());
+	assert_eq!(back.pads[0].abs_at, [11.0, 19.5]);
+	assert_eq!(back.pads[0].angle, 180.0);
+}
+
+#[test]
+fn test_footprint_s_expr() {
+	let test_string = "(footprint \"R\" (layer \"F.Cu\") (at 10 20 90) (fp_text reference \"R1\" (at 0 -2) (layer \"F.SilkS\"))
+		(pad \"1\" smd rect (at -1 0 90) (size 1 1) (layers \"F.Cu\") (net 1 \"GND\"))
+		(pad \"2\" smd rect (at 1 0.5 90) (size 1 1) (layers \"F.Cu\")))";
+
+	let exp = s_exp_parser::parse(test_string).unwrap();
+	let footprint = Footprint::from_exp(&exp.select("footprint")[0]).unwrap();
+
+	let mut written = SExpr::named("footprint");
+	footprint.write_into(&mut written);
+	assert_eq!(written.select("at")[0].values().len(), 3);
+	assert!(written.select("fp_text").is_empty());
+
+	let mut root = SExpr::new();
+	root.append_exp(written);
+	let reread = Footprint::from_exp(&s_exp_parser::parse(&root.print()).unwrap().select("footprint")[0]).unwrap();
+	assert_eq!(reread, footprint);
+	assert_eq!(reread.pads[1].abs_at, [10.5, 19.0]);
+}
+
+#[test]
+fn test_footprint_from_exp() {
+	// the nested at and layer come first here, only the footprint's own may be used
+	let test_string = "(footprint \"R\" (fp_text reference \"R1\" (at 0 -2) (layer \"F.SilkS\"))
+		(pad \"1\" smd rect (at -1 0) (size 1 1) (layers \"F.Cu\") (net 1 \"GND\"))
+		(layer \"F.Cu\") (at 10 20))";
+
+	let exp = s_exp_parser::parse(test_string).unwrap();
+	let footprint = Footprint::from_exp(&exp.select("footprint")[0]).unwrap();
+
+	assert_eq!(footprint.layer, "F.Cu");
+	assert_eq!(footprint.at, [10.0, 20.0]);
+	assert_eq!(footprint.pads.len(), 1);
+	assert_eq!(footprint.pads[0].abs_at, [9.0, 20.0]);
 }
 
 
@@ -394,6 +1089,8 @@ pub struct KicadPcb {
 	pub footprints : Vec<Footprint>,
 	pub wires : Vec<Wire>,
 	pub vias : Vec<Via>,
+	pub outline : Outline,
+	pub source : Option<String>, // text of the file the board was read from, used to write it back losslessly
 }
 
 
@@ -406,6 +1103,8 @@ impl KicadPcb {
 			footprints : Vec::new(),
 			wires : Vec::new(),
 			vias : Vec::new(),
+			outline : Outline::default(),
+			source : None,
 		}
 	}
 
@@ -420,8 +1119,8 @@ impl KicadPcb {
 		};
 
 		let epxrs = match s_exp_parser::parse(&data) {
-			Some(exp) => exp,
-			None => return Err(KicadPcbError::ParseFail),
+			Ok(exp) => exp,
+			Err(e) => return Err(KicadPcbError::ParseFail(e)),
 		};
 
 		let pcb_exp = epxrs.remove_trivial();
@@ -430,47 +1129,140 @@ impl KicadPcb {
 		// get all the relevant parts from the expression
 		//--------------------------------------------------
 		let pcb = KicadPcb{
-			general : match get_general(&pcb_exp) {
-				Ok(result) => result,
-				Err(e) => return Err(e),
-			},
+			general : get_general(&pcb_exp)?,
 
-			layers : match get_layers(&pcb_exp) {
-				Ok(result) => result,
-				Err(e) => return Err(e),
-			},
+			layers : get_layers(&pcb_exp)?,
 			
-			nets : match get_nets(&pcb_exp) {
-				Ok(result) => result,
-				Err(e) => return Err(e),
-			},
+			nets : get_nets(&pcb_exp)?,
 			
-			footprints : match get_footprints(&pcb_exp) {
-				Ok(result) => result,
-				Err(e) => return Err(e),
-			},
+			footprints : get_footprints(&pcb_exp)?,
 
-			wires : match get_wires(&pcb_exp) {
-				Ok(result) => result,
-				Err(e) => return Err(e),
-			},
+			wires : get_wires(&pcb_exp)?,
 
-			vias : match get_vias(&pcb_exp) {
-				Ok(result) => result,
-				Err(e) => return Err(e),
-			},
+			vias : get_vias(&pcb_exp)?,
+
+			outline : Outline::from_exp(&pcb_exp)?,
+
+			source : Some(data),
 		};
 
 		return Ok(pcb);
 	}
 
-	pub fn write_to_file(path : &str) {
-		unimplemented!();
+	pub fn write_to_file(&self, path : &str) -> Result<(), KicadPcbError> {
+		return match std::fs::write(path, self.to_file_string()?) {
+			Ok(()) => Ok(()),
+			Err(e) => Err(KicadPcbError::IoError(e)),
+		};
+	}
+
+
+	/// the text of the board file. boards read from a file are merged into that file,
+	/// anything else is generated from scratch with as_s_expr
+	pub fn to_file_string(&self) -> Result<String, KicadPcbError> {
+		if let Some(source) = &self.source {
+			return self.merge_into(source);
+		}
+
+		let mut root = SExpr::new();
+		root.append_exp(self.as_s_expr());
+		return Ok(root.pretty_print());
+	}
+
+
+	/// writes the tracks of the board into the text of a board file. segments and vias whose
+	/// tstamp isn't in the original are added, the ones of the original that are missing from
+	/// self are removed. everything else comes out byte for byte as it was
+	pub fn merge_into(&self, original : &str) -> Result<String, KicadPcbError> {
+		let mut document = match s_exp_parser::parse_lossless(original) {
+			Ok(d) => d,
+			Err(e) => return Err(KicadPcbError::ParseFail(e)),
+		};
+
+		let original_owned = document.to_exp();
+		let original_exp = original_owned.remove_trivial();
+
+		let original_wires = get_wires(&original_exp)?;
+		let original_vias = get_vias(&original_exp)?;
+
+		let original_tstamps : HashSet<&str> = original_wires.iter()
+			.map(|x| x.tstamp.as_str())
+			.chain(original_vias.iter().map(|x| x.tstamp.as_str()))
+			.collect();
+
+		let kept_tstamps : HashSet<&str> = self.wires.iter()
+			.map(|x| x.tstamp.as_str())
+			.chain(self.vias.iter().map(|x| x.tstamp.as_str()))
+			.collect();
+
+		let pcb = match document.root_mut().and_then(|x| x.children_mut()) {
+			Some(children) => children,
+			None => return Err(KicadPcbError::Other("no kicad_pcb expression in the file".to_string())),
+		};
+
+		// a removed node takes the line break and indentation in front of it along
+		pcb.retain(|node| {
+			if node.name() != Some("segment") && node.name() != Some("via") {
+				return true;
+			}
+
+			let tstamp = tstamp_of(&node.to_exp().view());
+			return tstamp.is_empty() || !original_tstamps.contains(tstamp.as_str()) || kept_tstamps.contains(tstamp.as_str());
+		});
+
+		// new nodes go on their own lines after the last child, indented like it
+		let indent = match pcb.iter().rev().find(|x| x.name().is_some()) {
+			Some(last) => match last.leading.rfind('\n') {
+				Some(newline) => last.leading[newline..].to_string(),
+				None => " ".to_string(),
+			},
+			None => "\n".to_string(),
+		};
+
+		let new_exps : Vec<SExpr> = self.vias.iter()
+			.filter(|x| !original_tstamps.contains(x.tstamp.as_str()))
+			.map(Via::as_s_expr)
+			.chain(self.wires.iter()
+				.filter(|x| !original_tstamps.contains(x.tstamp.as_str()))
+				.map(Wire::as_s_expr))
+			.collect();
+
+		for exp in new_exps.iter() {
+			pcb.push(LosslessNode::from_exp(exp, &indent));
+		}
+
+		return Ok(document.print());
 	}
 
 
-	fn as_s_expr(&self) -> SExpr {
-		unimplemented!();
+	/// the board as a kicad_pcb expression. footprints aren't modelled in enough detail
+	/// to be written back, so the result only holds the board setup, nets and tracks
+	pub fn as_s_expr(&self) -> SExpr<'static> {
+		let mut exp = SExpr::named("kicad_pcb");
+		exp.append_exp(SExpr::with_values("version", &[Value::Int(KICAD_FILE_VERSION)]));
+		exp.append_exp(SExpr::with_values("generator", &[Value::Symbol("crusty".into())]));
+
+		write_field(&mut exp, "general", &self.general);
+
+		let mut layers = SExpr::named("layers");
+		for layer in self.layers.iter() {
+			layers.append_exp(layer.as_s_expr());
+		}
+		exp.append_exp(layers);
+
+		for net in self.nets.iter() {
+			exp.append_exp(net.as_s_expr());
+		}
+
+		for via in self.vias.iter() {
+			exp.append_exp(via.as_s_expr());
+		}
+
+		for wire in self.wires.iter() {
+			exp.append_exp(wire.as_s_expr());
+		}
+
+		return exp;
 	}
 
 	pub fn get_layer_id(&self, name : &str) -> Option<LayerId> {
@@ -495,18 +1287,157 @@ impl KicadPcb {
 	}
 
 
-	pub fn route(&self, settings : &RouterSettings) -> Option<KicadPcb> {
-		// convert into abstract route graph
+	/// position of a layer among the routable layers, which is the layer
+	/// index used by the raster. None for non copper layers.
+	pub fn copper_layer_index(&self, name : &str) -> Option<usize> {
+		self.layers.iter()
+			.filter(|x| x.layer_type == LayerType::Signal)
+			.position(|x| x.name == name)
+	}
+
+
+	/// the layers patterns stand for on this board, see LayerSet
+	pub fn layer_set(&self, patterns : &[String]) -> LayerSet {
+		return LayerSet::expand(patterns, &self.layers);
+	}
+
+
+	/// raster layer indices of the copper layers in set, in order
+	pub fn copper_layer_indices(&self, set : &LayerSet) -> Vec<usize> {
+		let mut indices : Vec<usize> = set.iter()
+			.filter_map(|x| self.copper_layer_index(x))
+			.collect();
+		indices.sort_unstable();
+		return indices;
+	}
+
+
+	/// name of the layer at a raster layer index, the inverse of copper_layer_index
+	pub fn copper_layer_name(&self, index : usize) -> Option<String> {
+		self.layers.iter()
+			.filter(|x| x.layer_type == LayerType::Signal)
+			.nth(index)
+			.map(|x| x.name.clone())
+	}
+
+
+	/// everything that belongs to each net, for all nets that have anything at all
+	pub fn net_index(&self) -> BTreeMap<NetId, NetItems<'_>> {
+		let mut index : BTreeMap<NetId, NetItems> = BTreeMap::new();
+
+		for pad in self.footprints.iter().flat_map(|x| x.pads.iter()) {
+			index.entry(pad.net.id).or_default().pads.push(pad);
+		}
+
+		for wire in self.wires.iter() {
+			index.entry(wire.net_id).or_default().wires.push(wire);
+		}
+
+		for via in self.vias.iter() {
+			index.entry(via.net_id).or_default().vias.push(via);
+		}
+
+		return index;
+	}
+
+
+	/// everything that belongs to net, empty if there is nothing
+	pub fn net_items(&self, net : NetId) -> NetItems<'_> {
+		return NetItems{
+			pads : self.footprints.iter()
+				.flat_map(|x| x.pads.iter())
+				.filter(|x| x.net.id == net)
+				.collect(),
+			wires : self.wires.iter().filter(|x| x.net_id == net).collect(),
+			vias : self.vias.iter().filter(|x| x.net_id == net).collect(),
+		};
+	}
+
+
+	/// the lowest and highest corner of the area to route in. that is the board outline,
+	/// or without one the pads with PAD_MARGIN of room around them
+	pub fn bounding_box(&self) -> Option<[V2; 2]> {
+		if let Some(area) = self.outline.bounding_box() {
+			return Some(area);
+		}
+
+		return self.footprints.iter()
+			.flat_map(|x| x.pads.iter())
+			.map(|pad| {
+				let reach = pad.size[0].hypot(pad.size[1]) / 2.0 + PAD_MARGIN;
+				[
+					[pad.abs_at[0] - reach, pad.abs_at[1] - reach],
+					[pad.abs_at[0] + reach, pad.abs_at[1] + reach],
+				]
+			})
+			.reduce(|[low, high], [pad_low, pad_high]| [
+				[low[0].min(pad_low[0]), low[1].min(pad_low[1])],
+				[high[0].max(pad_high[0]), high[1].max(pad_high[1])],
+			]);
+	}
+
+
+	/// routes the board and returns a copy of it with the new wires and vias added
+	pub fn route(&self, settings : &RouterSettings) -> Result<RouteResult, KicadPcbError> {
+		// convert into the raster
+		let mut raster = Raster::new(self, settings);
 
 		//route it
+		let report = raster.route(settings);
 
-		//convert back from absctract route graph
+		//convert back from the raster
+		let (wires, vias) = raster.to_tracks(self, settings)?;
 
-		return None;
+		let mut board = self.clone();
+		board.wires.extend(wires);
+		board.vias.extend(vias);
+
+		return Ok(RouteResult{
+			board : board,
+			failed_nets : report.failed,
+			congestion : report.congestion,
+		});
 	}
 }
 
 
+/// the pads, wires and vias of one net, see KicadPcb::net_index
+#[derive(Debug, Clone, Default)]
+pub struct NetItems<'a> {
+	pub pads : Vec<&'a Pad>,
+	pub wires : Vec<&'a Wire>,
+	pub vias : Vec<&'a Via>,
+}
+
+
+#[test]
+fn test_net_index() {
+	let test_pcb = KicadPcb::from_file("./test_pcb/test_pcb.kicad_pcb").unwrap();
+	let index = test_pcb.net_index();
+
+	assert_eq!(index.keys().copied().collect::<Vec<NetId>>(), vec!{0, 1, 2, 3});
+
+	for net in 1..=3 {
+		assert_eq!(index[&net].pads.len(), 2);
+		assert!(index[&net].pads.iter().all(|x| x.net.id == net));
+	}
+
+	assert_eq!(index[&0].vias.len(), 1);
+	assert_eq!(index[&2].wires.len(), 2);
+	assert_eq!(test_pcb.net_items(1).pads[0].net.name, "GND");
+	assert!(test_pcb.net_items(42).pads.is_empty());
+}
+
+
+/// outcome of KicadPcb::route
+#[derive(Debug, Clone, Default)]
+pub struct RouteResult {
+	pub board : KicadPcb,
+	pub failed_nets : Vec<NetId>,
+	pub congestion : Vec<CongestionStats>, // only filled by the negotiated congestion algorithm
+}
+
+
 #[test]
 fn test_routable_layers() {
 	let test_pcb = KicadPcb::from_file("./test_pcb/test_pcb.kicad_pcb").unwrap();
@@ -514,11 +1445,100 @@ fn test_routable_layers() {
 	assert_eq!(test_pcb.routable_layers(), 2);
 }
 
+#[test]
+fn test_copper_layer_index() {
+	let test_pcb = KicadPcb::from_file("./test_pcb/test_pcb.kicad_pcb").unwrap();
+
+	assert_eq!(test_pcb.copper_layer_index("F.Cu"), Some(0));
+	assert_eq!(test_pcb.copper_layer_index("B.Cu"), Some(1));
+	assert_eq!(test_pcb.copper_layer_index("F.SilkS"), None);
+}
+
 
-#[derive(Debug, Copy, Clone, Default)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum Algorithm {
+	/// plain wave expansion, finds the shortest path in cells
+	#[default]
+	Lee,
+	/// best first search, finds the cheapest path under the given cost model
+	AStar(CostModel),
+	/// negotiated congestion, nets share cells at first and are pushed apart over iterations
+	Negotiated(NegotiationSettings),
+}
+
+
+#[derive(Debug, Copy, Clone)]
 pub struct RouterSettings {
 	pub max_passes : usize,
-	pub algorith : u8,
+	pub algorithm : Algorithm,
+	pub track_width : f64, // width of new wires in mm
+	pub via_size : f64,
+	pub via_drill : f64,
+	pub clearance : f64, // copper to copper distance between nets in mm, pads can set their own
+}
+
+impl Default for RouterSettings {
+	fn default() -> Self {
+		return Self{
+			max_passes : 0,
+			algorithm : Algorithm::default(),
+			track_width : 0.25,
+			via_size : 0.8,
+			via_drill : 0.4,
+			clearance : 0.2,
+		};
+	}
+}
+
+
+/// room left around the pads of a board without an outline, in mm
+const PAD_MARGIN : f64 = 1.0;
+
+/// file format version written into generated boards, the one of kicad 6
+const KICAD_FILE_VERSION : isize = 20211014;
+
+static TSTAMP_COUNTER : AtomicU64 = AtomicU64::new(0);
+
+/// a new random (version 4) uuid, as kicad uses them for tstamps
+pub fn new_tstamp() -> String {
+	let nanos = match SystemTime::now().duration_since(UNIX_EPOCH) {
+		Ok(d) => d.as_nanos() as u64,
+		Err(_) => 0,
+	};
+	let count = TSTAMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+
+	// splitmix64, good enough to keep tstamps apart, not meant to be secure
+	let mut state = nanos ^ count.wrapping_mul(0x9e3779b97f4a7c15);
+	let mut next = || {
+		state = state.wrapping_add(0x9e3779b97f4a7c15);
+		let mut z = state;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+		return z ^ (z >> 31);
+	};
+
+	let high = (next() & 0xffff_ffff_ffff_0fff) | 0x4000; // version 4
+	let low = (next() & 0x3fff_ffff_ffff_ffff) | 0x8000_0000_0000_0000; // variant 1
+
+	return format!(
+		"{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+		high >> 32,
+		(high >> 16) & 0xffff,
+		high & 0xffff,
+		low >> 48,
+		low & 0xffff_ffff_ffff,
+	);
+}
+
+#[test]
+fn test_new_tstamp() {
+	let a = new_tstamp();
+	let b = new_tstamp();
+
+	assert_ne!(a, b);
+	assert_eq!(a.len(), 36);
+	assert_eq!(a.split('-').map(|x| x.len()).collect::<Vec<usize>>(), vec![8, 4, 4, 4, 12]);
+	assert_eq!(&a[14..15], "4");
 }
 
 
@@ -527,49 +1547,190 @@ pub struct RouterSettings {
 fn test_route() {
 	let test_pcb = KicadPcb::from_file("./test_pcb/test_pcb.kicad_pcb").unwrap();
 	let settings = RouterSettings::default();
-	test_pcb.route(&settings).unwrap();
+	let result = test_pcb.route(&settings).unwrap();
+
+	assert!(result.failed_nets.is_empty());
+
+	let distance = |a : V2, b : V2| (a[0] - b[0]).hypot(a[1] - b[1]);
+	let to_segment = |p : V2, start : V2, end : V2| {
+		let d = [end[0] - start[0], end[1] - start[1]];
+		let length = d[0] * d[0] + d[1] * d[1];
+		let t = if length == 0.0 {0.0} else {(((p[0] - start[0]) * d[0] + (p[1] - start[1]) * d[1]) / length).clamp(0.0, 1.0)};
+		return distance(p, [start[0] + t * d[0], start[1] + t * d[1]]);
+	};
+
+	let new_wires : Vec<&Wire> = result.board.wires.iter()
+		.filter(|x| !test_pcb.wires.iter().any(|old| old.tstamp == x.tstamp))
+		.collect();
+	assert!(!new_wires.is_empty());
+
+	// net 2 is joined by the segments already on the board
+	assert!(new_wires.iter().all(|x| x.net_id != 2));
+
+	// every end of a new wire is on a pad, via or other wire of its net on the same layer
+	for wire in new_wires.iter() {
+		let items = result.board.net_items(wire.net_id);
+
+		for end in [wire.start, wire.end] {
+			let on_pad = items.pads.iter().any(|x| {
+				result.board.layer_set(&x.layer).contains(&wire.layer_name) && x.covers(end, 0.0)
+			});
+			let on_via = items.vias.iter().any(|x| distance(end, x.at) <= x.size / 2.0);
+			let on_wire = items.wires.iter().any(|x| {
+				!std::ptr::eq(*x, *wire) && x.layer_name == wire.layer_name && to_segment(end, x.start, x.end) <= x.width / 2.0
+			});
+
+			assert!(on_pad || on_via || on_wire, "{:?} ends at {:?} without touching its net", wire, end);
+		}
+	}
+
+	// and keeps the clearance to the pads and wires of all other nets
+	for wire in new_wires.iter() {
+		let reach = settings.clearance + wire.width / 2.0 - 1e-6;
+		let steps = 100;
+
+		for step in 0..=steps {
+			let t = step as f64 / steps as f64;
+			let at = [wire.start[0] + t * (wire.end[0] - wire.start[0]), wire.start[1] + t * (wire.end[1] - wire.start[1])];
+
+			for pad in result.board.footprints.iter().flat_map(|x| x.pads.iter()) {
+				let near = pad.net.id != wire.net_id
+					&& result.board.layer_set(&pad.layer).contains(&wire.layer_name)
+					&& pad.covers(at, pad.clearance.unwrap_or(settings.clearance) + wire.width / 2.0 - 1e-6);
+				assert!(!near, "{:?} comes too close to pad {:?}", wire, pad);
+			}
+
+			for other in result.board.wires.iter() {
+				let near = other.net_id != wire.net_id
+					&& other.layer_name == wire.layer_name
+					&& to_segment(at, other.start, other.end) < reach + other.width / 2.0;
+				assert!(!near, "{:?} comes too close to {:?}", wire, other);
+			}
+		}
+	}
 }
 
+#[test]
+fn test_as_s_expr() {
+	let test_pcb = KicadPcb::from_file("./test_pcb/test_pcb.kicad_pcb").unwrap();
 
+	let mut root = SExpr::new();
+	root.append_exp(test_pcb.as_s_expr());
+	let printed = root.print();
+	let reread = s_exp_parser::parse(&printed).unwrap();
 
-fn get_general(exp : &SExpr) -> Result<PcbGeneral, KicadPcbError> {
-	match exp.get("general")
-		.iter()
-		.filter_map(|x| 
-			match x.get_value("thickness") {
-				Some(Value::Float(f)) => Some(PcbGeneral{thickness : f}),
-				_ => None,
-		} )
-		.next() 
-	{
-		Some(v) => Ok(v),
-		None => Err(KicadPcbError::GeneralFail)
+	assert_eq!(get_layers(&reread.remove_trivial()).unwrap(), test_pcb.layers);
+	assert_eq!(get_nets(&reread.remove_trivial()).unwrap(), test_pcb.nets);
+	assert_eq!(get_wires(&reread.remove_trivial()).unwrap().len(), 2);
+	assert_eq!(get_vias(&reread.remove_trivial()).unwrap().len(), 1);
+	assert_eq!(get_general(&reread.remove_trivial()).unwrap().thickness, 1.6);
+}
+
+#[test]
+fn test_merge_into() {
+	let original = read_to_string("./test_pcb/test_pcb.kicad_pcb").unwrap();
+	let mut test_pcb = KicadPcb::from_file("./test_pcb/test_pcb.kicad_pcb").unwrap();
+
+	assert_eq!(test_pcb.merge_into(&original).unwrap(), original);
+
+	let mut new_wire = test_pcb.wires[0].clone();
+	new_wire.tstamp = new_tstamp();
+	test_pcb.wires.push(new_wire);
+
+	let merged = test_pcb.merge_into(&original).unwrap();
+	let added = LosslessNode::from_exp(&test_pcb.wires[2].as_s_expr(), "\n  ").print();
+
+	assert_eq!(merged.replacen(&added, "", 1), original);
+
+	// dropping a wire from the board removes its line from the file
+	let removed = test_pcb.wires.remove(0);
+	let merged = test_pcb.merge_into(&original).unwrap();
+	let removed_line = original.lines()
+		.find(|x| x.contains(&removed.tstamp))
+		.unwrap();
+
+	assert!(!merged.contains(&removed.tstamp));
+	assert_eq!(
+		merged.replacen(&added, "", 1),
+		original.replacen(&format!("\n{}", removed_line), "", 1)
+	);
+}
+
+#[test]
+fn test_merge_into_uuid() {
+	// newer files name tracks by uuid instead of tstamp
+	let original = "(kicad_pcb (version 20221018)
+  (segment (start 1 1) (end 2 1) (width 0.25) (layer \"F.Cu\") (net 1) (uuid 3ba3cee6-48b9-48e2-a466-821ec0c8a1c3))
+  (segment (start 2 1) (end 2 2) (width 0.25) (layer \"F.Cu\") (net 1) (uuid 5f0e8d2c-2f7e-4c1b-9d61-0e4a1f6b7c21))
+)
+";
+	let mut board = KicadPcb::new(SExpr::new());
+	board.wires = get_wires(&s_exp_parser::parse(original).unwrap().remove_trivial()).unwrap();
+	assert_eq!(board.merge_into(original).unwrap(), original);
+
+	board.wires.remove(0);
+	let merged = board.merge_into(original).unwrap();
+	assert!(!merged.contains("3ba3cee6-48b9-48e2-a466-821ec0c8a1c3"));
+	assert!(merged.contains("5f0e8d2c-2f7e-4c1b-9d61-0e4a1f6b7c21"));
+}
+
+#[test]
+fn test_from_file_parse_error() {
+	let path = std::env::temp_dir().join("crusty_test_parse_error.kicad_pcb");
+	std::fs::write(&path, "(kicad_pcb (version 20211014)\n  (net 0 \"\")\n").unwrap();
+
+	let error = KicadPcb::from_file(path.to_str().unwrap()).unwrap_err();
+	std::fs::remove_file(&path).unwrap();
+
+	match error {
+		KicadPcbError::ParseFail(e) => assert_eq!((e.line, e.column), (1, 1)),
+		other => panic!("{:?}", other),
 	}
 }
 
+#[test]
+fn test_copper_layer_name() {
+	let test_pcb = KicadPcb::from_file("./test_pcb/test_pcb.kicad_pcb").unwrap();
+
+	assert_eq!(test_pcb.copper_layer_name(0), Some("F.Cu".to_string()));
+	assert_eq!(test_pcb.copper_layer_name(1), Some("B.Cu".to_string()));
+	assert_eq!(test_pcb.copper_layer_name(2), None);
+}
+
+
+
+fn get_general(exp : &SExprRef) -> Result<PcbGeneral, KicadPcbError> {
+	return field(exp, "general").map_err(|e| KicadPcbError::field_fail("kicad_pcb", exp, e));
+}
+
 #[test]
 fn test_get_general() {
 	let test_pcb_general = get_general(
 		&s_exp_parser::parse(
 			&read_to_string("./test_pcb/test_pcb.kicad_pcb").unwrap()
-		).unwrap()
+		).unwrap().remove_trivial()
 	).unwrap();
 
 	assert_eq!(test_pcb_general.thickness, 1.6);
 }
 
 
-fn get_layers(exp : &SExpr) -> Result<Vec<PcbLayer>, KicadPcbError> {
-	let all_layers : Vec<PcbLayer> = exp.get("layers")[0]
+fn get_layers(exp : &SExprRef) -> Result<Vec<PcbLayer>, KicadPcbError> {
+	let layers = match exp.select("layers").first() {
+		Some(layers) => *layers,
+		None => return Err(KicadPcbError::field_fail("kicad_pcb", exp, FieldError::missing("layers"))),
+	};
+
+	let all_layers : Vec<PcbLayer> = layers
 		.sub_expressions()
 		.iter()
-		.map(PcbLayer::from_exp)
+		.map(|x| PcbLayer::from_exp(&x.view()))
 		.collect::<Result<Vec<PcbLayer>, KicadPcbError>>()?;
 
 	let mut deduped_layers : Vec<PcbLayer> = Vec::new();
 
 	for layer in all_layers.iter() {
-		if !deduped_layers.contains(&layer) {
+		if !deduped_layers.contains(layer) {
 			deduped_layers.push(layer.clone());
 		}
 	}
@@ -580,28 +1741,24 @@ fn get_layers(exp : &SExpr) -> Result<Vec<PcbLayer>, KicadPcbError> {
 
 #[test]
 fn test_get_layers() {
-	let test_pcb = &s_exp_parser::parse(
-		&read_to_string("./test_pcb/test_pcb.kicad_pcb").unwrap()
-	).unwrap();
+	let data = read_to_string("./test_pcb/test_pcb.kicad_pcb").unwrap();
+	let test_pcb = &s_exp_parser::parse(&data).unwrap();
 
-	let layers = get_layers(test_pcb).unwrap();
+	let layers = get_layers(&test_pcb.remove_trivial()).unwrap();
 
 	assert_eq!(layers.len(), 29);
 }
 
 
-fn get_nets(exp : &SExpr) -> Result<Vec<PcbNet>, KicadPcbError> {
+fn get_nets(exp : &SExprRef) -> Result<Vec<PcbNet>, KicadPcbError> {
 	let mut nets : Vec<PcbNet> = Vec::new();
 
-	// any mention of a net can be treated as a declaration as such
-	// there is no need to distinguish between declaration and other mentions
+	// only the declarations at the top level, the mentions in pads and tracks have no name
 	for net in exp
-		.get("net")
+		.select("net")
 		.iter()
-		.filter_map(|x| match PcbNet::from_exp(x) {
-			Ok(v) => Some(v),
-			_ => None,
-		})
+		.map(PcbNet::from_exp)
+		.collect::<Result<Vec<PcbNet>, KicadPcbError>>()?
 	{
 		if !nets.contains(&net) {
 			nets.push(net.clone());
@@ -613,19 +1770,18 @@ fn get_nets(exp : &SExpr) -> Result<Vec<PcbNet>, KicadPcbError> {
 
 #[test]
 fn test_get_nets() {
-	let test_pcb = &s_exp_parser::parse(
-		&read_to_string("./test_pcb/test_pcb.kicad_pcb").unwrap()
-	).unwrap();
+	let data = read_to_string("./test_pcb/test_pcb.kicad_pcb").unwrap();
+	let test_pcb = &s_exp_parser::parse(&data).unwrap();
 
-	let nets = get_nets(test_pcb).unwrap();
+	let nets = get_nets(&test_pcb.remove_trivial()).unwrap();
 
 	assert_eq!(nets.len(), 4);
 
 }
 
 
-fn get_footprints(exp : &SExpr) -> Result<Vec<Footprint>, KicadPcbError> {
-	exp.get("footprint")
+fn get_footprints(exp : &SExprRef) -> Result<Vec<Footprint>, KicadPcbError> {
+	exp.select("footprint")
 		.iter()
 		.map(Footprint::from_exp)
 		.collect()
@@ -634,20 +1790,21 @@ fn get_footprints(exp : &SExpr) -> Result<Vec<Footprint>, KicadPcbError> {
 
 #[test]
 fn test_get_footprints() {
-	let test_pcb = &s_exp_parser::parse(
-		&read_to_string("./test_pcb/test_pcb.kicad_pcb").unwrap()
-	).unwrap();
+	let data = read_to_string("./test_pcb/test_pcb.kicad_pcb").unwrap();
+	let test_pcb = &s_exp_parser::parse(&data).unwrap();
 
-	let footprints = get_footprints(&test_pcb).unwrap();
+	let footprints = get_footprints(&test_pcb.remove_trivial()).unwrap();
 
 	//panic!("{:?}", footprints);
 
 	assert_eq!(footprints.len(), 4);
+	assert_eq!(footprints[0].name, "Connector_Pin:Pin_D1.0mm_L10.0mm");
+	assert_eq!(footprints[0].layer, "F.Cu");
 }
 
 
-fn get_wires(exp : &SExpr) -> Result<Vec<Wire>, KicadPcbError> {
-	exp.get("segment")
+fn get_wires(exp : &SExprRef) -> Result<Vec<Wire>, KicadPcbError> {
+	exp.select("segment")
 		.iter()
 		.map(Wire::from_exp)
 		.collect()
@@ -656,18 +1813,17 @@ fn get_wires(exp : &SExpr) -> Result<Vec<Wire>, KicadPcbError> {
 
 #[test]
 fn test_get_wires() {
-	let test_pcb = &s_exp_parser::parse(
-		&read_to_string("./test_pcb/test_pcb.kicad_pcb").unwrap()
-	).unwrap();
+	let data = read_to_string("./test_pcb/test_pcb.kicad_pcb").unwrap();
+	let test_pcb = &s_exp_parser::parse(&data).unwrap();
 
-	let wires = get_wires(&test_pcb).unwrap();
+	let wires = get_wires(&test_pcb.remove_trivial()).unwrap();
 
 	assert_eq!(wires.len(), 2);
 }
 
 
-fn get_vias(exp : &SExpr) -> Result<Vec<Via>, KicadPcbError> {
-	exp.get("via")
+fn get_vias(exp : &SExprRef) -> Result<Vec<Via>, KicadPcbError> {
+	exp.select("via")
 		.iter()
 		.map(Via::from_exp)
 		.collect()	
@@ -675,11 +1831,10 @@ fn get_vias(exp : &SExpr) -> Result<Vec<Via>, KicadPcbError> {
 
 #[test]
 fn test_get_vias() {
-	let test_pcb = &s_exp_parser::parse(
-		&read_to_string("./test_pcb/test_pcb.kicad_pcb").unwrap()
-	).unwrap();
+	let data = read_to_string("./test_pcb/test_pcb.kicad_pcb").unwrap();
+	let test_pcb = &s_exp_parser::parse(&data).unwrap();
 
-	let vias = get_vias(&test_pcb).unwrap();
+	let vias = get_vias(&test_pcb.remove_trivial()).unwrap();
 
 	assert_eq!(vias.len(), 1);
 
@@ -1,13 +1,6 @@
-use crate::value::*;
-
-type HalfParsed = Vec<Either<Value, Delimeter>>;
-
-#[derive(Debug, Copy, Clone, PartialEq)]
-enum Delimeter {
-	Open,
-	Close,
-}
+use std::borrow::Cow;
 
+use crate::value::*;
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum Either<A, B> {
@@ -16,130 +9,622 @@ pub enum Either<A, B> {
 }
 
 
-type Element = Either<Value, Box<SExpr>>;
+type Element<'a> = Either<Value<'a>, Box<SExpr<'a>>>;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseErrorKind {
+	UnclosedParen,
+	StrayCloseParen,
+	UnterminatedString,
+}
+
+
+/// where and why parsing failed. line and column count from 1, column in characters
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+	pub kind : ParseErrorKind,
+	pub offset : usize, // in bytes
+	pub line : usize,
+	pub column : usize,
+	pub snippet : String, // the offending line with a caret under the position
+}
+
+
+impl ParseError {
+	pub fn new(kind : ParseErrorKind, source : &str, offset : usize) -> Self {
+		let line_start = source[..offset].rfind('\n').map(|x| x + 1).unwrap_or(0);
+		let line_end = source[offset..].find('\n').map(|x| x + offset).unwrap_or(source.len());
+		let before = &source[line_start..offset];
+
+		// keep tabs so the caret lines up with the text above it
+		let padding : String = before.chars()
+			.map(|c| if c == '\t' {'\t'} else {' '})
+			.collect();
+
+		return ParseError{
+			kind : kind,
+			offset : offset,
+			line : source[..line_start].matches('\n').count() + 1,
+			column : before.chars().count() + 1,
+			snippet : format!("{}\n{}^", source[line_start..line_end].trim_end_matches('\r'), padding),
+		};
+	}
+}
+
+
+impl std::fmt::Display for ParseError {
+	fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+		let what = match self.kind {
+			ParseErrorKind::UnclosedParen => "unclosed parenthesis",
+			ParseErrorKind::StrayCloseParen => "closing parenthesis without an opening one",
+			ParseErrorKind::UnterminatedString => "unterminated string",
+		};
+
+		write!(f, "{} at line {}, column {}\n{}", what, self.line, self.column, self.snippet)
+	}
+}
 
 
+/// lines pretty_print tries to stay within. lists without nested lists are never broken,
+/// so they may still be longer
+const PRETTY_WIDTH : usize = 99;
+
+/// lists pcbnew always breaks, however short they are
+const PRETTY_SECTIONS : [&str; 8] = ["kicad_pcb", "general", "layers", "setup", "stackup", "pcbplotparams", "title_block", "model"];
+
+/// lists pcbnew writes on one line however long they get, tracks and graphic lines
+const PRETTY_LINES : [&str; 11] = [
+	"segment", "arc", "via", "gr_line", "gr_arc", "gr_circle", "gr_rect",
+	"fp_line", "fp_arc", "fp_circle", "fp_rect",
+];
+
+/// children pcbnew keeps on the first line of a broken list, like (kicad_pcb (version ..) (generator ..)
+const PRETTY_HEADERS : [(&str, &[&str]); 2] = [
+	("kicad_pcb", &["version", "generator"]),
+	("footprint", &["layer"]),
+];
+
+
+/// a parsed expression. its atoms borrow their text from the parsed input,
+/// expressions built in code use 'static
 #[derive(Debug, PartialEq, Clone)]
-pub struct SExpr {
-	pub content : Vec<Element>
+pub struct SExpr<'a> {
+	pub content : Vec<Element<'a>>
 }
 
-impl SExpr {
+impl<'a> SExpr<'a> {
 	pub fn new() -> Self {
 		SExpr {
 			content : Vec::new(),
 		}
 	}
 
-	pub fn append_exp(&mut self, s_expression : SExpr) {
+	/// an expression holding only its name, children are appended afterwards
+	pub fn named(name : &str) -> Self {
+		let mut exp = SExpr::new();
+		exp.append_value(Value::Symbol(Cow::Owned(name.to_string())));
+		return exp;
+	}
+
+	/// an expression of a name followed by values, like (at 1.0 2.0)
+	pub fn with_values(name : &str, values : &[Value<'a>]) -> Self {
+		let mut exp = SExpr::named(name);
+		for value in values.iter() {
+			exp.append_value(value.clone());
+		}
+		return exp;
+	}
+
+	pub fn append_exp(&mut self, s_expression : SExpr<'a>) {
 		self.content.push(Either::That(Box::new(s_expression)));
 	}
 
-	pub fn append_value(&mut self, value : Value) {
+	pub fn append_value(&mut self, value : Value<'a>) {
 		self.content.push(Either::This(value));
 	}
 
+	/// a borrowed view of the whole expression, which all queries go through
+	pub fn view(&self) -> SExprRef<'_> {
+		return SExprRef{content : &self.content};
+	}
+
 	pub fn print(&self) -> String {
-		self.iter()
+		return self.view().print();
+	}
+
+	/// the expression laid out the way pcbnew writes files. a list stays on one line if it
+	/// holds no nested lists, is one of PRETTY_LINES, or fits into the line width and isn't one
+	/// of PRETTY_SECTIONS. a broken list keeps its leading values and PRETTY_HEADERS children on the first line and
+	/// puts every other child on a line of its own, two spaces further in, closed on a separate line
+	pub fn pretty_print(&self) -> String {
+		let mut out = String::new();
+
+		for element in self.iter() {
+			match element {
+				Either::This(value) => out.push_str(&value_as_string(value)),
+				Either::That(exp) => exp.pretty_into(&mut out, 0),
+			}
+			out.push('\n');
+		}
+
+		return out;
+	}
+
+	fn pretty_into(&self, out : &mut String, indent : usize) {
+		let inline = self.inline();
+		let name = self.get_name();
+		let section = PRETTY_SECTIONS.contains(&name.as_str());
+		let fits = 2 * indent + inline.len() + 2 <= PRETTY_WIDTH;
+
+		if self.depth() == 0 || PRETTY_LINES.contains(&name.as_str()) || (!section && fits) {
+			out.push('(');
+			out.push_str(&inline);
+			out.push(')');
+			return;
+		}
+
+		let mut header : Vec<String> = self.iter()
+			.map_while(|x| match x {
+				Either::This(value) => Some(value_as_string(value)),
+				Either::That(_) => None,
+			})
+			.collect();
+
+		let kept : &[&str] = PRETTY_HEADERS.iter()
+			.find(|(list, _)| *list == name)
+			.map_or(&[], |(_, children)| children);
+
+		while let Some(Either::That(exp)) = self.content.get(header.len()) {
+			if !kept.contains(&exp.get_name().as_str()) {
+				break;
+			}
+			header.push(format!("({})", exp.inline()));
+		}
+
+		out.push('(');
+		out.push_str(&header.join(" "));
+
+		for element in self.content[header.len()..].iter() {
+			out.push('\n');
+			out.push_str(&"  ".repeat(indent + 1));
+			match element {
+				Either::This(value) => out.push_str(&value_as_string(value)),
+				Either::That(exp) => exp.pretty_into(out, indent + 1),
+			}
+		}
+
+		out.push('\n');
+		out.push_str(&"  ".repeat(indent));
+		out.push(')');
+	}
+
+	// the expression on one line, single spaces between elements
+	fn inline(&self) -> String {
+		return self.iter()
 			.map(|x| match x {
-				Either::This(value) => format!(" {} ", value_as_string(value)),
-				Either::That(sub_exp) => format!("({})", sub_exp.print()),
+				Either::This(value) => value_as_string(value),
+				Either::That(exp) => format!("({})", exp.inline()),
 			})
-			.collect()
+			.collect::<Vec<String>>()
+			.join(" ");
+	}
+
+	// how many levels of lists are nested inside this one
+	fn depth(&self) -> usize {
+		return self.sub_expressions().iter()
+			.map(|x| x.depth() + 1)
+			.max()
+			.unwrap_or(0);
 	}
 
 	pub fn get_name(&self) -> String {
-		if self.content.len() == 0 {
-			return String::new();
+		return self.view().get_name();
+	}
+
+	pub fn get(&self, name : &str) -> Vec<SExprRef<'_>> {
+		return self.view().get(name);
+	}
+
+	// gets the first single value associated with name
+	pub fn get_value(&self, name : &str) -> Option<&Value<'_>> {
+		return self.view().get_value(name);
+	}
+
+	fn is_trivial(&self) -> bool {
+		return self.view().is_trivial();
+	}
+
+	/// see SExprRef::remove_trivial
+	pub fn remove_trivial(&self) -> SExprRef<'_> {
+		return self.view().remove_trivial();
+	}
+
+	pub fn iter(&self) -> std::slice::Iter<'_, Element<'a>> {
+		return self.content.iter();
+	}
+
+	pub fn values(&self) -> Vec<&Value<'_>> {
+		return self.view().values();
+	}
+
+	pub fn sub_expressions(&self) -> Vec<&SExpr<'_>> {
+		return self.view().sub_expressions();
+	}
+
+	/// see SExprRef::select
+	pub fn select(&self, path : &str) -> Vec<SExprRef<'_>> {
+		return self.view().select(path);
+	}
+
+	/// see SExprRef::value
+	pub fn value(&self, path : &str) -> Option<&Value<'_>> {
+		return self.view().value(path);
+	}
+
+	/// the first expression at path, with its name, to edit it in place.
+	/// paths work like with select
+	pub fn find_mut(&mut self, path : &str) -> Option<&mut SExpr<'a>> {
+		let location = self.locate(path).into_iter().next()?;
+		return self.at_location(&location);
+	}
+
+	/// calls f on every expression at path, returns how many there were
+	pub fn for_each_mut<F : FnMut(&mut SExpr<'a>)>(&mut self, path : &str, mut f : F) -> usize {
+		let locations = self.locate(path);
+
+		for location in locations.iter() {
+			if let Some(exp) = self.at_location(location) {
+				f(exp);
+			}
 		}
-		return match &self.content[0] {
-			Either::This(value) => value_as_string(value),
-			Either::That(_) => String::new(), 
-		};
+
+		return locations.len();
 	}
 
-	pub fn get(&self, name : &str) -> Vec<SExpr>{
-		if self.get_name() == name {
-			return vec!{SExpr{content : self.content[1..].to_vec()}};
-		} else {
-			return self.iter()
-				.filter_map(|x| match x {
-					Either::That(exp) => Some(exp.get(name)),
-					_ => None,
-				})
-				.flatten()
-				.collect();
+	/// removes every expression at path, returns how many were removed
+	pub fn remove(&mut self, path : &str) -> usize {
+		let mut locations = self.locate(path);
+
+		// back to front, so removing one doesn't shift the positions of the others
+		locations.sort_unstable_by(|a, b| b.cmp(a));
+
+		for location in locations.iter() {
+			let (last, parent) = match location.split_last() {
+				Some(split) => split,
+				None => continue,
+			};
+
+			if let Some(parent) = self.at_location(parent) {
+				parent.content.remove(*last);
+			}
 		}
+
+		return locations.len();
 	}
 
+	/// inserts exp right after the last direct child named sibling, or at the end if there is none
+	pub fn insert_after(&mut self, sibling : &str, exp : SExpr<'a>) {
+		let position = self.content.iter()
+			.rposition(|x| match x {
+				Either::That(child) => child.view().name() == Some(sibling),
+				Either::This(_) => false,
+			});
 
-	// gets the first single value associated with name
-	pub fn get_value(&self, name : &str) -> Option<Value> {
-		let gotten = self.get(name);
+		let element = Either::That(Box::new(exp));
+		match position {
+			Some(p) => self.content.insert(p + 1, element),
+			None => self.content.push(element),
+		}
+	}
 
-		if gotten.is_empty() { return None;}
+	/// overwrites a value of this expression and returns the old one. position counts the
+	/// values after the name, like the last step of a value path
+	pub fn set_value(&mut self, position : usize, value : Value<'a>) -> Option<Value<'a>> {
+		let old = self.content.iter_mut()
+			.skip(1)
+			.filter_map(|x| match x {
+				Either::This(v) => Some(v),
+				Either::That(_) => None,
+			})
+			.nth(position)?;
 
-		return match &gotten[0].content[0] {
-			Either::This(value) => Some(value.clone()),
-			Either::That(_ ) => None,
+		return Some(std::mem::replace(old, value));
+	}
+
+	/// replaces every value equal to old, at any depth, with new. returns how many were replaced
+	pub fn replace_value(&mut self, old : &Value, new : &Value<'a>) -> usize {
+		let mut count = 0;
+
+		for element in self.content.iter_mut() {
+			match element {
+				Either::This(v) => if &*v == old {
+					*v = new.clone();
+					count += 1;
+				},
+				Either::That(child) => count += child.replace_value(old, new),
+			}
+		}
+
+		return count;
+	}
+
+	// positions in content, one per level, of every expression at path.
+	// finding them first keeps the mutable walks simple
+	fn locate(&self, path : &str) -> Vec<Vec<usize>> {
+		let mut found = Vec::new();
+		self.locate_steps(&PathStep::parse(path), &mut Vec::new(), &mut found);
+
+		found.sort_unstable();
+		found.dedup();
+		return found;
+	}
+
+	fn locate_steps(&self, steps : &[PathStep], at : &mut Vec<usize>, found : &mut Vec<Vec<usize>>) {
+		let (step, rest) = match steps.split_first() {
+			Some(split) => split,
+			None => {
+				found.push(at.clone());
+				return;
+			},
 		};
 
+		for (i, element) in self.content.iter().enumerate() {
+			if let Either::That(child) = element {
+				at.push(i);
+
+				if step.matches(child) {
+					child.locate_steps(rest, at, found);
+				}
+
+				if step.descendant {
+					child.locate_steps(steps, at, found);
+				}
+
+				at.pop();
+			}
+		}
 	}
 
+	fn at_location(&mut self, location : &[usize]) -> Option<&mut SExpr<'a>> {
+		let mut exp = self;
 
-	fn is_trivial(&self) -> bool {
-		if self.content.len() < 2 {
-			return match self.content[0] {
-				Either::This(_) => false,
-				Either::That(_) => true, //in case the 1 element is an expression
+		for i in location.iter() {
+			exp = match exp.content.get_mut(*i)? {
+				Either::That(child) => child,
+				Either::This(_) => return None,
 			};
 		}
 
-		return false;
+		return Some(exp);
+	}
+}
+
+
+impl PartialEq<SExprRef<'_>> for SExpr<'_> {
+	fn eq(&self, other : &SExprRef) -> bool {
+		self.content == other.content
+	}
+}
+
+
+/// a borrowed view of (a part of) an expression. queries on it hand out references
+/// into the tree they were made from, so nothing gets copied while searching
+#[derive(Debug, Clone, Copy)]
+pub struct SExprRef<'a> {
+	pub content : &'a [Element<'a>],
+}
+
+impl<'a> SExprRef<'a> {
+	pub fn iter(&self) -> std::slice::Iter<'a, Element<'a>> {
+		return self.content.iter();
+	}
+
+	pub fn print(&self) -> String {
+		self.iter()
+			.map(|x| match x {
+				Either::This(value) => format!(" {} ", value_as_string(value)),
+				Either::That(sub_exp) => format!("({})", sub_exp.print()),
+			})
+			.collect()
+	}
+
+	pub fn get_name(&self) -> String {
+		return match self.content.first() {
+			Some(Either::This(value)) => value_as_string(value),
+			_ => String::new(),
+		};
+	}
+
+	/// the leading symbol, like net for (net 1 "GND")
+	fn name(&self) -> Option<&'a str> {
+		return match self.content.first() {
+			Some(Either::This(Value::Symbol(name))) => Some(name),
+			_ => None,
+		};
+	}
+
+	/// every expression named name at any depth, each without its name.
+	/// use select to only look at specific levels
+	pub fn get(&self, name : &str) -> Vec<SExprRef<'a>> {
+		let mut found = Vec::new();
+		self.collect_named(name, &mut found);
+		return found;
+	}
+
+	fn collect_named(&self, name : &str, found : &mut Vec<SExprRef<'a>>) {
+		if self.name() == Some(name) {
+			found.push(SExprRef{content : &self.content[1..]});
+			return;
+		}
+
+		for element in self.content.iter() {
+			if let Either::That(exp) = element {
+				exp.view().collect_named(name, found);
+			}
+		}
 	}
 
+	// gets the first single value associated with name
+	pub fn get_value(&self, name : &str) -> Option<&'a Value<'a>> {
+		return match self.get(name).first()?.content.first()? {
+			Either::This(value) => Some(value),
+			Either::That(_ ) => None,
+		};
+	}
 
-	/// returns a new s expression, with empty outer nestings trimmed away.
+	fn is_trivial(&self) -> bool {
+		return self.content.len() == 1 && matches!(self.content[0], Either::That(_)); //in case the 1 element is an expression
+	}
+
+	/// returns the expression with empty outer nestings trimmed away.
 	/// i.e. ((((a b c)))) becomes a b c
 	/// not actuall sure if this should be here or somewhere else, like router.rs
 	/// as it is fairly specific to the application, but also much nicer to write as a method
-	pub fn remove_trivial(&self) -> SExpr {
+	pub fn remove_trivial(&self) -> SExprRef<'a> {
 		if self.is_trivial() {
-			let trimmed_exp = match &self.content[0] {
+			return match &self.content[0] {
 				Either::This(_) => panic!("SExpr.is_trivial didn't categorize correctly. this should be unreachable."),
-				Either::That(inner) => *inner.clone(),
+				Either::That(inner) => inner.view().remove_trivial(),
 			};
-
-			return trimmed_exp.remove_trivial();
 		}
 
-		return self.clone();
+		return *self;
 	}
 
-
-	pub fn iter(&self) -> std::slice::Iter<Element> {
-		return self.content.iter();
-	}
-
-	pub fn values(&self) -> Vec<Value>{
+	pub fn values(&self) -> Vec<&'a Value<'a>> {
 		self.iter()
 			.filter_map(|x| match x {
-				Either::This(value) => Some(value.clone()),
-				Either::That(_) => None,				
+				Either::This(value) => Some(value),
+				Either::That(_) => None,
 			})
 			.collect()
 	}
 
-	pub fn sub_expressions(&self) -> Vec<SExpr> {
+	pub fn sub_expressions(&self) -> Vec<&'a SExpr<'a>> {
 		self.iter()
 			.filter_map(|x| match x {
-				Either::That(exp) => Some(*exp.clone()),
+				Either::That(exp) => Some(&**exp),
 				Either::This(_) => None,
 			})
 			.collect()
 	}
+
+	/// a copy of the tree, for when the expression has to outlive it. atoms keep borrowing
+	/// from the input
+	pub fn to_exp(self) -> SExpr<'a> {
+		return SExpr{content : self.content.to_vec()};
+	}
+
+	/// the expressions at the end of path, each without its name like with get.
+	/// steps are separated by / for direct children or // for descendants at any depth.
+	/// a step is a name or * for any name, optionally followed by [child] to only keep
+	/// expressions that have a direct child of that name. i.e. pad[net]/at on a footprint
+	/// is the position of every pad that is connected to a net
+	pub fn select(&self, path : &str) -> Vec<SExprRef<'a>> {
+		let mut current = vec!{*self};
+
+		for step in PathStep::parse(path).iter() {
+			let mut next = Vec::new();
+			for exp in current.iter() {
+				exp.select_step(step, &mut next);
+			}
+			current = next;
+		}
+
+		return current;
+	}
+
+	fn select_step(&self, step : &PathStep, found : &mut Vec<SExprRef<'a>>) {
+		for child in self.sub_expressions() {
+			if step.matches(child) {
+				found.push(SExprRef{content : &child.content[1..]});
+			}
+
+			if step.descendant {
+				child.view().select_step(step, found);
+			}
+		}
+	}
+
+	/// a single value addressed by path, where the last step is the position of the value.
+	/// i.e. at/2 is the angle of (at x y angle). if path matches more than one expression,
+	/// the first one is used
+	pub fn value(&self, path : &str) -> Option<&'a Value<'a>> {
+		let (nodes, position) = path.rsplit_once('/')?;
+		let position : usize = position.parse().ok()?;
+
+		return self.select(nodes).first()?.values().get(position).copied();
+	}
 }
 
+
+/// one step of a select path, like pad[net]
+struct PathStep<'p> {
+	name : &'p str,
+	has_child : Option<&'p str>,
+	descendant : bool, // matches at any depth instead of only direct children
+}
+
+impl<'p> PathStep<'p> {
+	fn parse(path : &'p str) -> Vec<Self> {
+		let mut steps = Vec::new();
+		let mut rest = path;
+
+		while !rest.is_empty() {
+			let descendant = rest.starts_with("//");
+			rest = rest.trim_start_matches('/');
+
+			let end = rest.find('/').unwrap_or(rest.len());
+			let step = &rest[..end];
+			rest = &rest[end..];
+
+			steps.push(match step.split_once('[') {
+				Some((name, filter)) => PathStep{
+					name : name,
+					has_child : Some(filter.trim_end_matches(']')),
+					descendant : descendant,
+				},
+				None => PathStep{name : step, has_child : None, descendant : descendant},
+			});
+		}
+
+		return steps;
+	}
+
+	/// if the whole expression exp, including its name, is matched by this step
+	fn matches(&self, exp : &SExpr) -> bool {
+		let name = match exp.view().name() {
+			Some(name) => name,
+			None => return false,
+		};
+
+		if self.name != "*" && self.name != name {
+			return false;
+		}
+
+		return match self.has_child {
+			Some(child) => exp.sub_expressions().iter().any(|x| x.view().name() == Some(child)),
+			None => true,
+		};
+	}
+}
+
+
+impl PartialEq for SExprRef<'_> {
+	fn eq(&self, other : &Self) -> bool {
+		self.content == other.content
+	}
+}
+
+
+impl PartialEq<SExpr<'_>> for SExprRef<'_> {
+	fn eq(&self, other : &SExpr) -> bool {
+		self.content == other.content
+	}
+}
+
+
 #[test]
 fn test_get() {
 	let test_string = "(test (\nnesting 1 2 3.5)\n (nesting 1 2 3.5\n) \n(nesting 1 2 3.5\n) string)".to_string();
@@ -154,7 +639,7 @@ fn test_print() {
 	let test_string = "(test (nesting 1 2 3.5) string)".to_string();
 	let test_expr = parse(&test_string).unwrap();
 
-	assert_eq!(parse(&test_expr.print()), Some(test_expr));
+	assert_eq!(parse(&test_expr.print()), Ok(test_expr));
 }
 
 
@@ -164,7 +649,7 @@ fn test_is_trivial() {
 	let test_string = "(test (nesting 1 2 3.5) string)".to_string();
 	let test_expr = parse(&test_string).unwrap();
 
-	assert_eq!(test_expr.is_trivial(), true);
+	assert!(test_expr.is_trivial());
 
 	//TODO: more test data
 }
@@ -177,14 +662,14 @@ fn test_remove_trivial() {
 
 	let test_res = SExpr{
 		content : vec!{
-			Either::This(Value::String("test".to_string())),
+			Either::This(Value::Symbol("test".into())),
 			Either::That(Box::new(SExpr{content : vec!{
-				Either::This(Value::String("nesting".to_string())),
+				Either::This(Value::Symbol("nesting".into())),
 				Either::This(Value::Int(1)),
 				Either::This(Value::Int(2)),
 				Either::This(Value::Float(3.5)),
 			}})),
-			Either::This(Value::String("string".to_string()))
+			Either::This(Value::Symbol("string".into()))
 		},
 	};
 
@@ -235,47 +720,266 @@ fn test_sexpr_get() {
 
 }
 
+#[test]
+fn test_select() {
+	let test_expr = parse("(footprint \"R\" (at 1 2 90) (fp_text value (at 0 3))
+		(pad 1 (at -1 0) (net 1 \"GND\")) (pad 2 (at 1 0)))").unwrap();
+	let footprint = test_expr.select("footprint")[0];
+
+	assert_eq!(footprint.select("at").len(), 1);
+	assert_eq!(footprint.select("//at").len(), 4);
+	assert_eq!(footprint.select("pad/at").len(), 2);
+	assert_eq!(footprint.select("pad[net]/at").len(), 1);
+	assert_eq!(footprint.select("pad[net]/at")[0].values(), vec!{&Value::Int(-1), &Value::Int(0)});
+	assert_eq!(footprint.select("*/at").len(), 3);
+	assert_eq!(footprint.select("pad/net")[0].values()[1], &Value::QuotedString("GND".into()));
+	assert!(footprint.select("net").is_empty());
+
+	assert_eq!(footprint.value("at/2"), Some(&Value::Int(90)));
+	assert_eq!(test_expr.value("footprint/pad/at/0"), Some(&Value::Int(-1)));
+	assert_eq!(footprint.value("at/3"), None);
+	assert_eq!(footprint.value("at"), None);
+}
+
+#[test]
+fn test_pretty_print() {
+	let test_expr = parse("(kicad_pcb (version 20211014) (general (thickness 1.6))
+		(footprint \"R\" (layer \"F.Cu\") (at 1.0 -2.50)
+		(fp_text value \"R1\" (at 0 0) (effects (font (size 1 1))) (tstamp 6a1cd587-d3ac-47b6-bb04-2ee7b13eea9e))
+		(pad 1 smd rect (at 0.5 0) (net 1 \"GND\")))
+		(segment (start 0 0) (end 1.25 0.1) (width 0.25) (layer \"F.Cu\") (net 1) (tstamp 3ba3cee6-48b9-48e2-a466-821ec0c8a1c3)))").unwrap();
+
+	let expected = "(kicad_pcb (version 20211014)
+  (general
+    (thickness 1.6)
+  )
+  (footprint \"R\" (layer \"F.Cu\")
+    (at 1 -2.5)
+    (fp_text value \"R1\"
+      (at 0 0)
+      (effects (font (size 1 1)))
+      (tstamp 6a1cd587-d3ac-47b6-bb04-2ee7b13eea9e)
+    )
+    (pad 1 smd rect (at 0.5 0) (net 1 \"GND\"))
+  )
+  (segment (start 0 0) (end 1.25 0.1) (width 0.25) (layer \"F.Cu\") (net 1) (tstamp 3ba3cee6-48b9-48e2-a466-821ec0c8a1c3))
+)
+";
+
+	assert_eq!(test_expr.pretty_print(), expected);
+	assert_eq!(parse(expected).unwrap().pretty_print(), expected);
+
+	// the parts of a board come out the way pcbnew wrote them
+	let file = std::fs::read_to_string("./test_pcb/test_pcb.kicad_pcb").unwrap();
+	let board = parse(&file).unwrap();
+	let board = board.remove_trivial();
+
+	let mut header = SExpr::named("kicad_pcb");
+	for child in board.sub_expressions().into_iter().take(3) {
+		header.append_exp(child.clone());
+	}
+	let mut root = SExpr::new();
+	root.append_exp(header);
+	assert!(root.pretty_print().starts_with("(kicad_pcb (version 20211014) (generator pcbnew)\n  (general\n"));
+
+	for child in board.sub_expressions() {
+		if !["general", "layers", "net", "segment", "via"].contains(&child.get_name().as_str()) {
+			continue;
+		}
+		let mut printed = "\n  ".to_string();
+		child.pretty_into(&mut printed, 1);
+		assert!(file.contains(&printed), "{}", printed);
+	}
+}
+
+#[test]
+fn test_edit() {
+	let mut test_expr = parse("(kicad_pcb (net 1 \"GND\")
+		(segment (width 0.25) (net 1)) (segment (width 0.25) (net 2)) (via (net 1)))").unwrap();
+
+	let via = test_expr.find_mut("kicad_pcb/via").unwrap();
+	assert_eq!(via.set_value(0, Value::Int(5)), None);
+	assert_eq!(via.find_mut("net").unwrap().set_value(0, Value::Int(2)), Some(Value::Int(1)));
+	assert_eq!(test_expr.value("kicad_pcb/via/net/0"), Some(&Value::Int(2)));
+
+	let bumped = test_expr.for_each_mut("kicad_pcb/segment/width", |x| {
+		x.set_value(0, Value::Float(0.5));
+	});
+	assert_eq!(bumped, 2);
+	assert_eq!(test_expr.select("//width").iter().filter(|x| x.values() == vec!{&Value::Float(0.5)}).count(), 2);
+
+	let pcb = test_expr.find_mut("kicad_pcb").unwrap();
+	pcb.insert_after("net", SExpr::with_values("net", &[Value::Int(2), Value::QuotedString("VCC".into())]));
+	pcb.insert_after("zone", SExpr::named("zone"));
+	assert_eq!(pcb.sub_expressions()[1].view().values()[2], &Value::QuotedString("VCC".into()));
+	assert_eq!(pcb.sub_expressions().last().unwrap().get_name(), "zone");
+
+	assert_eq!(test_expr.remove("kicad_pcb/segment[net]"), 2);
+	assert_eq!(test_expr.remove("//net"), 3);
+	assert!(test_expr.select("kicad_pcb/segment").is_empty());
+	assert_eq!(test_expr.select("kicad_pcb/*").len(), 2);
+
+	assert_eq!(test_expr.replace_value(&Value::Symbol("zone".into()), &Value::Symbol("keepout".into())), 1);
+	assert_eq!(test_expr.select("kicad_pcb/keepout").len(), 1);
+}
+
+#[test]
+fn test_sexpr_view() {
+	let test_expr = parse("(pad 1 (at 2 3) (net 4 \"GND\"))").unwrap();
+	let pad = test_expr.get("pad")[0];
+
+	// queries hand out references into the parsed tree instead of copies
+	let number = match &test_expr.sub_expressions()[0].content[1] {
+		Either::This(value) => value,
+		Either::That(_) => panic!("pad number should be a value"),
+	};
+	assert!(std::ptr::eq(pad.values()[0], number));
+
+	assert_eq!(pad.get_value("net"), Some(&Value::Int(4)));
+	assert_eq!(pad.get("net")[0].values(), vec!{&Value::Int(4), &Value::QuotedString("GND".into())});
+	assert_eq!(pad.sub_expressions().len(), 2);
+	assert_eq!(pad.get("at")[0].to_exp(), SExpr{content : vec!{Either::This(Value::Int(2)), Either::This(Value::Int(3))}});
+
+	// atoms point into the parsed text, only strings with escapes are copied
+	let text = "(net 4 \"GND\" \"a\\\"b\")";
+	let test_expr = parse(text).unwrap();
+	let values = test_expr.get("net")[0].values();
+	match values[1] {
+		Value::QuotedString(Cow::Borrowed(name)) => assert!(text.as_bytes().as_ptr_range().contains(&name.as_ptr())),
+		other => panic!("{:?} should borrow from the input", other),
+	}
+	assert_eq!(values[2], &Value::QuotedString(Cow::Owned("a\"b".to_string())));
+	assert!(matches!(values[2], Value::QuotedString(Cow::Owned(_))));
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind<'s> {
+	Open,
+	Close,
+	Atom(&'s str),
+}
+
 
-pub fn parse (s : &str) -> Option<SExpr> {
-	let mut leveled_values : HalfParsed = Vec::new();
-	let mut chunk = String::new();
+/// a token together with the whitespace in front of it, both slices of the input
+#[derive(Debug, Clone, PartialEq)]
+struct Token<'s> {
+	leading : &'s str,
+	kind : TokenKind<'s>,
+	offset : usize, // byte offset of the token in the input
+}
+
+
+/// splits s into parentheses and atoms, keeping all whitespace, and hands them to emit in
+/// order. this is the only place that knows about strings and escapes, both parsers are built
+/// on it. stops at the first error of emit, returns the whitespace after the last token
+fn tokenize<'s>(s : &'s str, mut emit : impl FnMut(Token<'s>) -> Result<(), ParseError>) -> Result<&'s str, ParseError> {
+	let mut leading_start = 0; // where the whitespace in front of the next token starts
+	let mut atom_start : Option<usize> = None;
 	let mut in_string = false;
+	let mut escaped = false; // the last character was a backslash inside a string
+	let mut string_start = 0;
 
-	for c in s.chars() {
-		if c == '\"' {in_string = !in_string;}
-		
+	for (i, c) in s.char_indices() {
 		if in_string {
-			chunk.push(c);
+			if escaped {
+				escaped = false;
+			} else if c == '\\' {
+				escaped = true;
+			} else if c == '\"' {
+				in_string = false;
+			}
+			continue;
 		}
 
-		else if c.is_whitespace() && !chunk.is_empty() {
-			leveled_values.push(Either::This(turn_to_value(&chunk)));
-			chunk = String::new();
+		if c == '\"' {
+			in_string = true;
+			string_start = i;
 		}
 
-		else if c == '(' {
-			leveled_values.push(Either::This(turn_to_value(&chunk)));
-			chunk = String::new();
-			leveled_values.push(Either::That(Delimeter::Open));
+		if c == '\"' || !(c.is_whitespace() || c == '(' || c == ')') {
+			atom_start.get_or_insert(i);
+			continue;
 		}
 
-		else if c == ')' {
-			leveled_values.push(Either::This(turn_to_value(&chunk)));
-			chunk = String::new();
-			leveled_values.push(Either::That(Delimeter::Close));
+		if let Some(start) = atom_start.take() {
+			emit(Token{leading : &s[leading_start..start], kind : TokenKind::Atom(&s[start..i]), offset : start})?;
+			leading_start = i;
 		}
 
-		else {
-			chunk.push(c);
+		if c == '(' || c == ')' {
+			let kind = if c == '(' {TokenKind::Open} else {TokenKind::Close};
+			emit(Token{leading : &s[leading_start..i], kind : kind, offset : i})?;
+			leading_start = i + 1;
 		}
 	}
 
-	if !chunk.is_empty() {
-		leveled_values.push(Either::This(turn_to_value(&chunk)));
+	if in_string {
+		return Err(ParseError::new(ParseErrorKind::UnterminatedString, s, string_start));
 	}
 
+	if let Some(start) = atom_start {
+		emit(Token{leading : &s[leading_start..start], kind : TokenKind::Atom(&s[start..]), offset : start})?;
+		leading_start = s.len();
+	}
+
+	return Ok(&s[leading_start..]);
+}
 
-	return Some(merge_into_exp(leveled_values));
+
+/// parses s in a single pass over its tokens. every list is built on a stack while it is open
+/// and moved into its parent when it closes, so nothing is scanned twice or copied on the way up.
+/// the atoms borrow their text from s
+pub fn parse (s : &str) -> Result<SExpr<'_>, ParseError> {
+	// the lists that are still open, with the offset of their opening parenthesis
+	let mut stack : Vec<(usize, SExpr)> = vec!{(0, SExpr::new())};
+
+	tokenize(s, |token| {
+		match token.kind {
+			TokenKind::Open => stack.push((token.offset, SExpr::new())),
+			TokenKind::Atom(text) => append_atom(&mut stack.last_mut().unwrap().1, text),
+			TokenKind::Close => {
+				if stack.len() < 2 {
+					return Err(ParseError::new(ParseErrorKind::StrayCloseParen, s, token.offset));
+				}
+				let (_, finished) = stack.pop().unwrap();
+				stack.last_mut().unwrap().1.append_exp(finished);
+			},
+		}
+		return Ok(());
+	})?;
+
+	if stack.len() > 1 {
+		return Err(ParseError::new(ParseErrorKind::UnclosedParen, s, stack[stack.len() - 1].0));
+	}
+
+	return Ok(stack.pop().unwrap().1);
+}
+
+
+fn append_atom<'a>(exp : &mut SExpr<'a>, text : &'a str) {
+	let value = turn_to_value(text);
+	if value != Value::None {
+		exp.append_value(value);
+	}
+}
+
+
+#[test]
+fn test_parse_errors() {
+	let unclosed = parse("(a\n\t(b (c))").unwrap_err();
+	assert_eq!(unclosed.kind, ParseErrorKind::UnclosedParen);
+	assert_eq!((unclosed.offset, unclosed.line, unclosed.column), (0, 1, 1));
+
+	let stray = parse("(a)\n  (b))").unwrap_err();
+	assert_eq!(stray.kind, ParseErrorKind::StrayCloseParen);
+	assert_eq!((stray.offset, stray.line, stray.column), (9, 2, 6));
+	assert_eq!(stray.snippet, "  (b))\n     ^");
+
+	let string = parse("(a \"b)").unwrap_err();
+	assert_eq!(string.kind, ParseErrorKind::UnterminatedString);
+	assert_eq!((string.offset, string.line, string.column), (3, 1, 4));
+	assert_eq!(string.to_string(), "unterminated string at line 1, column 4\n(a \"b)\n   ^");
 }
 
 
@@ -283,16 +987,16 @@ pub fn parse (s : &str) -> Option<SExpr> {
 fn test_parse() {
 	let test_string = "test (nesting 1 2 3.5) string".to_string();
 
-	let test_res = Some(SExpr{
+	let test_res = Ok(SExpr{
 		content : vec!{
-			Either::This(Value::String("test".to_string())),
+			Either::This(Value::Symbol("test".into())),
 			Either::That(Box::new(SExpr{content : vec!{
-				Either::This(Value::String("nesting".to_string())),
+				Either::This(Value::Symbol("nesting".into())),
 				Either::This(Value::Int(1)),
 				Either::This(Value::Int(2)),
 				Either::This(Value::Float(3.5)),
 			}})),
-			Either::This(Value::String("string".to_string()))
+			Either::This(Value::Symbol("string".into()))
 		},
 	});
 	assert_eq!(test_res, parse(&test_string));
@@ -300,104 +1004,416 @@ fn test_parse() {
 
 
 
-fn merge_into_exp(leveled_values : HalfParsed) -> SExpr {
-	let mut res = SExpr::new();
-	let mut i = 0;
+#[test]
+fn test_parse_escapes() {
+	let test_string = r#"(net 1 "a \"(quoted)\" \\ name") (net 2 "b")"#;
+	let test_expr = parse(test_string).unwrap();
+
+	assert_eq!(test_expr.get("net").len(), 2);
+	assert_eq!(
+		test_expr.get("net")[0].values()[1],
+		&Value::QuotedString("a \"(quoted)\" \\ name".into())
+	);
+	assert_eq!(parse(&test_expr.print()), Ok(test_expr));
+}
 
-	while i < leveled_values.len() {
-		match &leveled_values[i] {
-			Either::This(value) => {
-				if value != &Value::None {
-					res.append_value(value.clone());
+
+#[test]
+fn test_tokenize() {
+	let collect = |s| {
+		let mut tokens = Vec::new();
+		let trailing = tokenize(s, |x| {
+			tokens.push(x);
+			return Ok(());
+		}).unwrap();
+		return (tokens, trailing);
+	};
+
+	let (tokens, trailing) = collect(" (a \"b c\")\n");
+
+	assert_eq!(tokens, vec!{
+		Token{leading : " ", kind : TokenKind::Open, offset : 1},
+		Token{leading : "", kind : TokenKind::Atom("a"), offset : 2},
+		Token{leading : " ", kind : TokenKind::Atom("\"b c\""), offset : 4},
+		Token{leading : "", kind : TokenKind::Close, offset : 9},
+	});
+	assert_eq!(trailing, "\n");
+
+	let (tokens, _) = collect(r#"("a\"b" "c\\")"#);
+	assert_eq!(tokens.len(), 4);
+	assert_eq!(tokens[1].kind, TokenKind::Atom(r#""a\"b""#));
+	assert_eq!(tokens[2].kind, TokenKind::Atom(r#""c\\""#));
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LosslessKind {
+	/// a single token as it was spelled in the input
+	Atom(String),
+	/// a parenthesized list and the whitespace in front of its closing parenthesis
+	List(Vec<LosslessNode>, String),
+}
+
+
+/// a node of a losslessly parsed expression, remembering the whitespace in front of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct LosslessNode {
+	pub leading : String,
+	pub kind : LosslessKind,
+}
+
+
+impl LosslessNode {
+	/// a node with kicads usual single space layout, for inserting new content into a parsed file
+	pub fn from_exp(exp : &SExpr, leading : &str) -> Self {
+		let children = exp.iter()
+			.enumerate()
+			.map(|(i, x)| {
+				let child_leading = if i == 0 {""} else {" "};
+				match x {
+					Either::This(value) => LosslessNode{
+						leading : child_leading.to_string(),
+						kind : LosslessKind::Atom(value_as_string(value)),
+					},
+					Either::That(sub_exp) => LosslessNode::from_exp(sub_exp, child_leading),
 				}
-				i += 1;
-			},
+			})
+			.collect();
 
-			Either::That(Delimeter::Open) => {
-				let closing_brace = get_closing_delim(&leveled_values, i);
-				let sub_exp = merge_into_exp(leveled_values[i + 1 .. closing_brace].to_vec());
-				res.append_exp(sub_exp);
-				i = closing_brace;
+		return LosslessNode{
+			leading : leading.to_string(),
+			kind : LosslessKind::List(children, String::new()),
+		};
+	}
+
+	/// the first atom of a list, like segment for (segment ...)
+	pub fn name(&self) -> Option<&str> {
+		return match &self.kind {
+			LosslessKind::List(children, _) => match children.first() {
+				Some(LosslessNode{kind : LosslessKind::Atom(text), ..}) => Some(text),
+				_ => None,
 			},
+			LosslessKind::Atom(_) => None,
+		};
+	}
 
-			_ => {i += 1;},
+	pub fn children(&self) -> &[LosslessNode] {
+		return match &self.kind {
+			LosslessKind::List(children, _) => children,
+			LosslessKind::Atom(_) => &[],
 		};
 	}
 
-	return res;
+	/// the children of a list, None for atoms
+	pub fn children_mut(&mut self) -> Option<&mut Vec<LosslessNode>> {
+		return match &mut self.kind {
+			LosslessKind::List(children, _) => Some(children),
+			LosslessKind::Atom(_) => None,
+		};
+	}
+
+	pub fn print(&self) -> String {
+		let mut out = String::new();
+		self.print_into(&mut out);
+		return out;
+	}
+
+	fn print_into(&self, out : &mut String) {
+		out.push_str(&self.leading);
+		match &self.kind {
+			LosslessKind::Atom(text) => out.push_str(text),
+			LosslessKind::List(children, trailing) => {
+				out.push('(');
+				for child in children.iter() {
+					child.print_into(out);
+				}
+				out.push_str(trailing);
+				out.push(')');
+			},
+		}
+	}
+
+	/// the node as a plain expression, as parse would have returned it
+	pub fn to_exp(&self) -> SExpr<'_> {
+		let mut exp = SExpr::new();
+		for child in self.children().iter() {
+			child.append_to(&mut exp);
+		}
+		return exp;
+	}
+
+	fn append_to<'a>(&'a self, exp : &mut SExpr<'a>) {
+		match &self.kind {
+			LosslessKind::Atom(text) => {
+				let value = turn_to_value(text);
+				if value != Value::None {
+					exp.append_value(value);
+				}
+			},
+			LosslessKind::List(..) => exp.append_exp(self.to_exp()),
+		}
+	}
+}
+
+
+/// an expression parsed so that printing it gives back the input byte for byte.
+/// only the parts that get edited or replaced print differently
+#[derive(Debug, Clone, PartialEq)]
+pub struct LosslessExpr {
+	pub nodes : Vec<LosslessNode>,
+	pub trailing : String,
+}
+
+
+impl LosslessExpr {
+	pub fn print(&self) -> String {
+		let mut out = String::new();
+		for node in self.nodes.iter() {
+			node.print_into(&mut out);
+		}
+		out.push_str(&self.trailing);
+		return out;
+	}
+
+	/// the whole input as a plain expression, equal to what parse returns
+	pub fn to_exp(&self) -> SExpr<'_> {
+		let mut exp = SExpr::new();
+		for node in self.nodes.iter() {
+			node.append_to(&mut exp);
+		}
+		return exp;
+	}
+
+	/// the first top level list, for a board file the kicad_pcb expression
+	pub fn root_mut(&mut self) -> Option<&mut LosslessNode> {
+		return self.nodes.iter_mut()
+			.find(|x| matches!(x.kind, LosslessKind::List(..)));
+	}
+}
+
+
+/// parses s keeping whitespace and the spelling of every token
+pub fn parse_lossless(s : &str) -> Result<LosslessExpr, ParseError> {
+	// open lists, each with its offset, the whitespace in front of it and its children so far
+	let mut stack : Vec<(usize, String, Vec<LosslessNode>)> = vec!{(0, String::new(), Vec::new())};
+
+	let trailing = tokenize(s, |token| {
+		match token.kind {
+			TokenKind::Open => stack.push((token.offset, token.leading.to_string(), Vec::new())),
+			TokenKind::Atom(text) => stack.last_mut().unwrap().2.push(LosslessNode{
+				leading : token.leading.to_string(),
+				kind : LosslessKind::Atom(text.to_string()),
+			}),
+			TokenKind::Close => {
+				if stack.len() < 2 {
+					return Err(ParseError::new(ParseErrorKind::StrayCloseParen, s, token.offset));
+				}
+				let (_, leading, children) = stack.pop().unwrap();
+				stack.last_mut().unwrap().2.push(LosslessNode{
+					leading : leading,
+					kind : LosslessKind::List(children, token.leading.to_string()),
+				});
+			},
+		}
+		return Ok(());
+	})?;
+
+	if stack.len() > 1 {
+		return Err(ParseError::new(ParseErrorKind::UnclosedParen, s, stack[stack.len() - 1].0));
+	}
+
+	return Ok(LosslessExpr{
+		nodes : stack.pop().unwrap().2,
+		trailing : trailing.to_string(),
+	});
 }
 
 
 #[test]
-fn test_merge_into_exp() {
-	let test_res = SExpr{
-		content : vec!{
-			Either::This(Value::String("test".to_string())),
-			Either::That(Box::new(SExpr{content : vec!{
-				Either::This(Value::String("nesting".to_string())),
-				Either::This(Value::Int(1)),
-				Either::This(Value::Int(2)),
-				Either::This(Value::Float(3.5)),
-			}})),
-			Either::This(Value::String("string".to_string()))
-		},
-	};
+fn test_parse_lossless() {
+	let test_string = "\t(test\n  (nesting 1   2 3.50)\n  \"a  string\" ( ) )\n\n";
+	let lossless = parse_lossless(test_string).unwrap();
 
-	let test_list = vec!{
-		Either::This(Value::String("test".to_string())),
-		Either::That(Delimeter::Open),		
-		Either::This(Value::String("nesting".to_string())),
-		Either::This(Value::Int(1)),
-		Either::This(Value::Int(2)),
-		Either::This(Value::Float(3.5)),
-		Either::That(Delimeter::Close),
-		Either::This(Value::String("string".to_string()))
-	};
+	assert_eq!(lossless.print(), test_string);
+	assert_eq!(Ok(lossless.to_exp()), parse(test_string));
 
-	assert_eq!(test_res, merge_into_exp(test_list));
+	assert_eq!(parse_lossless("(a (b)").unwrap_err().kind, ParseErrorKind::UnclosedParen);
+	assert_eq!(parse_lossless("(a))").unwrap_err().offset, 3);
+	assert_eq!(parse_lossless("(a \"b)").unwrap_err().kind, ParseErrorKind::UnterminatedString);
 }
 
 
+#[test]
+fn test_parse_lossless_board() {
+	let data = std::fs::read_to_string("./test_pcb/test_pcb.kicad_pcb").unwrap();
+	let lossless = parse_lossless(&data).unwrap();
 
-/// returns the index of the delimter closing the one at opening
-fn get_closing_delim(list : &HalfParsed, opening : usize) -> usize {
-	let mut level = 0;
-	let mut index = opening;
+	assert_eq!(lossless.print(), data);
+	assert_eq!(Ok(lossless.to_exp()), parse(&data));
+}
 
-	while index < list.len() {
-		match list[index] {
-			Either::This(_) => {},
-			Either::That(Delimeter::Open) => {
-				level += 1;
+
+#[test]
+fn test_lossless_edit() {
+	let test_string = "(test\n  (a 1)\n  (b 2)\n)";
+	let mut lossless = parse_lossless(test_string).unwrap();
+
+	let root = lossless.root_mut().unwrap();
+	let children = root.children_mut().unwrap();
+	children.retain(|x| x.name() != Some("a"));
+	children.push(LosslessNode::from_exp(&SExpr::with_values("c", &[Value::Float(3.5)]), "\n  "));
+
+	assert_eq!(lossless.print(), "(test\n  (b 2)\n  (c 3.5)\n)");
+}
+
+
+/// a board with the given number of footprints, each nested like the ones pcbnew writes
+#[cfg(test)]
+fn generate_board(footprints : usize) -> String {
+	let mut board = String::from("(kicad_pcb (version 20211014) (generator pcbnew)\n  (general\n    (thickness 1.6)\n  )\n");
+
+	for i in 0..footprints {
+		let x = (i % 100) as f64 * 2.54;
+		let y = (i / 100) as f64 * 2.54;
+
+		board.push_str(&format!("  (footprint \"Resistor_SMD:R_0603_1608Metric\" (layer \"F.Cu\")\n    (tstamp {:08x}-0000-4000-8000-000000000000)\n    (at {} {} 90)\n", i, x, y));
+		board.push_str("    (fp_text reference \"R\" (at 0 -1.43 90) (layer \"F.SilkS\")\n      (effects (font (size 1 1) (thickness 0.15)))\n    )\n");
+
+		for pad in 1..=2 {
+			board.push_str(&format!("    (pad \"{}\" smd roundrect (at {} 0 90) (size 0.8 0.95) (layers \"F.Cu\" \"F.Paste\" \"F.Mask\") (roundrect_rratio 0.25)\n      (net {} \"N{}\") (pintype \"passive\"))\n", pad, if pad == 1 {-0.825} else {0.825}, i % 50 + 1, i % 50 + 1));
+		}
+
+		board.push_str("    (model \"${KICAD6_3DMODEL_DIR}/Resistor_SMD.3dshapes/R_0603_1608Metric.wrl\"\n      (offset (xyz 0 0 0))\n      (scale (xyz 1 1 1))\n      (rotate (xyz 0 0 0))\n    )\n  )\n");
+	}
+
+	board.push_str(")\n");
+	return board;
+}
+
+
+/// the parser this file started with, kept to compare against. it finds the closing
+/// parenthesis of every list by scanning ahead and parses a copy of the tokens in between,
+/// so it gets slower the deeper lists are nested
+#[cfg(test)]
+fn parse_recursive(s : &str) -> SExpr<'_> {
+	let mut leveled_values : Vec<Either<Value, bool>> = Vec::new(); // true opens a list
+	let mut chunk_start : Option<usize> = None;
+	let mut in_string = false;
+
+	for (i, c) in s.char_indices() {
+		if c == '\"' {in_string = !in_string;}
+
+		if in_string || !(c.is_whitespace() || c == '(' || c == ')') {
+			chunk_start.get_or_insert(i);
+			continue;
+		}
+
+		if let Some(start) = chunk_start.take() {
+			leveled_values.push(Either::This(turn_to_value(&s[start..i])));
+		}
+		if c == '(' || c == ')' {
+			leveled_values.push(Either::That(c == '('));
+		}
+	}
+
+	if let Some(start) = chunk_start {
+		leveled_values.push(Either::This(turn_to_value(&s[start..])));
+	}
+
+	return merge_into_exp(leveled_values);
+}
+
+#[cfg(test)]
+fn merge_into_exp(leveled_values : Vec<Either<Value, bool>>) -> SExpr {
+	let mut res = SExpr::new();
+	let mut i = 0;
+
+	while i < leveled_values.len() {
+		match &leveled_values[i] {
+			Either::This(value) => {
+				if value != &Value::None {
+					res.append_value(value.clone());
+				}
+				i += 1;
 			},
-			Either::That(Delimeter::Close) => {
-				level -= 1;
+			Either::That(true) => {
+				let closing = get_closing_delim(&leveled_values, i);
+				res.append_exp(merge_into_exp(leveled_values[i + 1 .. closing].to_vec()));
+				i = closing;
 			},
-		};
+			Either::That(false) => i += 1,
+		}
+	}
+
+	return res;
+}
 
+#[cfg(test)]
+fn get_closing_delim(list : &[Either<Value, bool>], opening : usize) -> usize {
+	let mut level = 0;
+
+	for (index, element) in list.iter().enumerate().skip(opening) {
+		match element {
+			Either::That(true) => level += 1,
+			Either::That(false) => level -= 1,
+			Either::This(_) => {},
+		}
 		if level == 0 {
 			return index;
 		}
-
-		index += 1;
 	}
 
-	return index;
+	return list.len();
+}
+
+
+/// lists nested depth deep, repeated until the text is about bytes long
+#[cfg(test)]
+fn generate_nested(depth : usize, bytes : usize) -> String {
+	let list = format!("{}x{}\n", "(n ".repeat(depth), ")".repeat(depth));
+	return list.repeat((bytes / list.len()).max(1));
 }
 
 
+/// cargo test --release bench_parse -- --ignored --nocapture
+///
+/// on the generated boards the old parser takes about one and a half times as long, and each
+/// level of nesting adds to its time while the new one stays flat:
+///  1000 footprints,  0.7 MB:   old   19.1 ms, new   13.1 ms,  1.5x
+///  4000 footprints,  3.0 MB:   old   74.0 ms, new   45.8 ms,  1.6x
+/// 16000 footprints, 11.9 MB:   old  280.0 ms, new  185.1 ms,  1.5x
+///     depth    1,  0.2 MB:   old    6.9 ms, new    5.2 ms,  1.3x
+///     depth   10,  0.2 MB:   old    8.6 ms, new    5.8 ms,  1.5x
+///     depth  100,  0.2 MB:   old   41.6 ms, new    5.4 ms,  7.8x
+///     depth  500,  0.2 MB:   old  160.7 ms, new    5.3 ms, 30.4x
 #[test]
-fn test_get_closing_delim() {
-	let test_list = vec!{
-		Either::This(Value::String("test".to_string())),
-		Either::That(Delimeter::Open),		
-		Either::This(Value::String("nesting".to_string())),
-		Either::This(Value::Int(1)),
-		Either::This(Value::Int(2)),
-		Either::This(Value::Float(3.5)),
-		Either::That(Delimeter::Close),
-		Either::This(Value::String("string".to_string()))
+#[ignore]
+fn bench_parse() {
+	let report = |what : String, text : &str| {
+		let start = std::time::Instant::now();
+		let old_exp = parse_recursive(text);
+		let old = start.elapsed().as_secs_f64() * 1e3;
+
+		let start = std::time::Instant::now();
+		let new_exp = parse(text).unwrap();
+		let new = start.elapsed().as_secs_f64() * 1e3;
+
+		assert_eq!(old_exp, new_exp);
+		println!("{}, {:>4.1} MB:   old {:>6.1} ms, new {:>6.1} ms, {:>4.1}x", what, text.len() as f64 / 1e6, old, new, old / new);
+		return old / new;
 	};
 
-	assert_eq!(get_closing_delim(&test_list, 1), 6);
-}
\ No newline at end of file
+	for footprints in [1000, 4000, 16000] {
+		let ratio = report(format!("{:>5} footprints", footprints), &generate_board(footprints));
+		assert!(ratio > 1.0);
+	}
+
+	let mut ratios = Vec::new();
+	for depth in [1, 10, 100, 500] {
+		ratios.push(report(format!("    depth {:>4}", depth), &generate_nested(depth, 200_000)));
+	}
+
+	// the old parser is quadratic in the nesting depth, the new one linear in the size
+	assert!(ratios.windows(2).all(|x| x[1] > x[0]));
+	assert!(ratios[3] > 10.0 * ratios[0]);
+}
@@ -1,31 +1,48 @@
+use std::borrow::Cow;
+
+
+/// an atom of an expression. text borrows from the parsed input where it can,
+/// only quoted strings with escapes in them need a copy
 #[derive(Debug, Clone, PartialEq)]
-pub enum Value {
+pub enum Value<'a> {
 	None,
 	Float(f64),
-	String(String),
+	Symbol(Cow<'a, str>), // bare word, like segment or *.Cu
+	QuotedString(Cow<'a, str>), // content of a "quoted string", without the quotes
 	Int(isize),
 }
 
-
-pub fn turn_to_value(s : &str) -> Value {
+pub fn turn_to_value(s : &str) -> Value<'_> {
 	let trimmed = s.trim_start()
 		.trim_end()
 		.trim_end_matches(")")
 		.trim_start_matches("(");
-	
 
-	if let Ok(maybe_int) = trimmed.parse::<isize>() {
-		return Value::Int(maybe_int);
-	};
+	if trimmed.len() >= 2 && trimmed.starts_with('"') && trimmed.ends_with('"') {
+		let content = &trimmed[1..trimmed.len() - 1];
+		if content.contains('\\') {
+			return Value::QuotedString(Cow::Owned(unescape(content)));
+		}
+		return Value::QuotedString(Cow::Borrowed(content));
+	}
+
+	// only try the number parsers on things that can be numbers, most atoms are words
+	let numeric = trimmed.starts_with(|c : char| c.is_ascii_digit() || c == '-' || c == '+' || c == '.');
 
+	if numeric {
+		if let Ok(maybe_int) = trimmed.parse::<isize>() {
+			return Value::Int(maybe_int);
+		};
 
-	if let Ok(maybe_float) = trimmed.parse::<f64>() {
-		return Value::Float(maybe_float);
+
+		if let Ok(maybe_float) = trimmed.parse::<f64>() {
+			return Value::Float(maybe_float);
+		}
 	}
 
 
 	if !trimmed.is_empty() {
-		return Value::String(trimmed.to_string());
+		return Value::Symbol(Cow::Borrowed(trimmed));
 	}
 
 	return Value::None;
@@ -36,8 +53,11 @@ fn test_turn_to_value() {
 	assert_eq!(turn_to_value(""), Value::None);
 	assert_eq!(turn_to_value("()"), Value::None);
 	assert_eq!(turn_to_value("("), Value::None);
-	assert_eq!(turn_to_value("test"), Value::String("test".to_string()));
-	assert_eq!(turn_to_value(" test "), Value::String("test".to_string()));
+	assert_eq!(turn_to_value("test"), Value::Symbol("test".into()));
+	assert_eq!(turn_to_value(" test "), Value::Symbol("test".into()));
+	assert_eq!(turn_to_value("\"F.Cu\""), Value::QuotedString("F.Cu".into()));
+	assert_eq!(turn_to_value("\"\""), Value::QuotedString("".into()));
+	assert_eq!(turn_to_value("\"1\""), Value::QuotedString("1".into()));
 	assert_eq!(turn_to_value("42"), Value::Int(42));
 	assert_eq!(turn_to_value(" 42 "), Value::Int(42));
 	assert_eq!(turn_to_value("4.2"), Value::Float(4.2));
@@ -47,22 +67,46 @@ fn test_turn_to_value() {
 pub fn value_as_string(v : &Value) -> String {
 	match v {
 		Value::None => String::new(),
-		Value::String(s) => s.clone(),
-		Value::Int(s) => format!("{:?}", s),
-		Value::Float(s) => format!("{:?}", s),
+		Value::Symbol(s) => s.to_string(),
+		Value::QuotedString(s) => quote(s),
+		Value::Int(s) => format!("{}", s),
+		Value::Float(s) => format_float(*s),
 	}
 }
 
+/// floats like pcbnew writes them: at most 6 decimals, no trailing zeros, so 1.0 becomes 1
+pub fn format_float(f : f64) -> String {
+	let fixed = format!("{:.6}", f);
+	let trimmed = fixed.trim_end_matches('0').trim_end_matches('.');
+
+	if trimmed == "-0" {
+		return "0".to_string();
+	}
+
+	return trimmed.to_string();
+}
+
+#[test]
+fn test_format_float() {
+	assert_eq!(format_float(1.0), "1");
+	assert_eq!(format_float(-2.5), "-2.5");
+	assert_eq!(format_float(0.1 + 0.2), "0.3");
+	assert_eq!(format_float(37.7625), "37.7625");
+	assert_eq!(format_float(100.0), "100");
+	assert_eq!(format_float(-0.0000001), "0");
+	assert_eq!(format_float(1e-6), "0.000001");
+}
+
 #[test]
 fn test_value_as_string() {
-	let test_cases : Vec<String>= vec!{
+	let test_cases : Vec<String>= [
 		"",
 		"42",
 		"hello world"
-	}.iter().map(|x| x.to_string()).collect();
+	].iter().map(|x| x.to_string()).collect();
 
 	for case in test_cases.iter() {
-		assert_eq!(&value_as_string(&turn_to_value(&case)), case);
+		assert_eq!(&value_as_string(&turn_to_value(case)), case);
 	}
 }
 
@@ -83,7 +127,102 @@ pub fn value_to_int(v : &Value) -> Option<isize> {
 
 pub fn value_to_string(v : &Value) -> Option<String> {
 	match v {
-		Value::String(s) => Some(s.clone()),
+		Value::Symbol(s) => Some(s.to_string()),
+		Value::QuotedString(s) => Some(s.to_string()),
 		_ => None,
 	}
-}
\ No newline at end of file
+}
+
+/// resolves the escape sequences kicad uses inside quoted strings.
+/// unknown escapes keep the escaped character
+pub fn unescape(s : &str) -> String {
+	let mut result = String::with_capacity(s.len());
+	let mut chars = s.chars().peekable();
+
+	while let Some(c) = chars.next() {
+		if c != '\\' {
+			result.push(c);
+			continue;
+		}
+
+		let escaped = match chars.next() {
+			Some(e) => e,
+			None => {
+				result.push('\\');
+				break;
+			},
+		};
+
+		match escaped {
+			'n' => result.push('\n'),
+			'r' => result.push('\r'),
+			't' => result.push('\t'),
+			'a' => result.push('\x07'),
+			'b' => result.push('\x08'),
+			'f' => result.push('\x0c'),
+			'v' => result.push('\x0b'),
+			'x' => {
+				let mut code = String::new();
+				while code.len() < 2 && chars.peek().is_some_and(|d| d.is_ascii_hexdigit()) {
+					code.push(chars.next().unwrap());
+				}
+				match u32::from_str_radix(&code, 16).ok().and_then(char::from_u32) {
+					Some(d) => result.push(d),
+					None => result.push('x'),
+				}
+			},
+			'0'..='7' => {
+				let mut code = escaped.to_string();
+				while code.len() < 3 && chars.peek().is_some_and(|d| ('0'..='7').contains(d)) {
+					code.push(chars.next().unwrap());
+				}
+				match u32::from_str_radix(&code, 8).ok().and_then(char::from_u32) {
+					Some(d) => result.push(d),
+					None => result.push_str(&code),
+				}
+			},
+			other => result.push(other),
+		}
+	}
+
+	return result;
+}
+
+#[test]
+fn test_unescape() {
+	assert_eq!(unescape("plain"), "plain");
+	assert_eq!(unescape(r#"a \"b\""#), "a \"b\"");
+	assert_eq!(unescape(r"back\\slash"), "back\\slash");
+	assert_eq!(unescape(r"line\nbreak\ttab"), "line\nbreak\ttab");
+	assert_eq!(unescape(r"\x41\101"), "AA");
+	assert_eq!(unescape(r"\q"), "q");
+}
+
+
+/// puts s in quotes, escaping what kicad escapes when writing
+pub fn quote(s : &str) -> String {
+	let mut result = String::with_capacity(s.len() + 2);
+	result.push('"');
+
+	for c in s.chars() {
+		match c {
+			'"' => result.push_str("\\\""),
+			'\\' => result.push_str("\\\\"),
+			'\n' => result.push_str("\\n"),
+			'\r' => result.push_str("\\r"),
+			'\t' => result.push_str("\\t"),
+			other => result.push(other),
+		}
+	}
+
+	result.push('"');
+	return result;
+}
+
+#[test]
+fn test_quote() {
+	assert_eq!(quote("F.Cu"), "\"F.Cu\"");
+	assert_eq!(quote("a \"b\" \\ c\n"), r#""a \"b\" \\ c\n""#);
+	assert_eq!(turn_to_value(&quote("any \"text\"\\\n")), Value::QuotedString("any \"text\"\\\n".into()));
+	assert_eq!(turn_to_value(&quote("Net-(D0-Pad2)")), Value::QuotedString("Net-(D0-Pad2)".into()));
+}